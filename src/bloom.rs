@@ -0,0 +1,36 @@
+//! Bloom filter of hashes, used to tell (up to false positives) whether a k-mer was seen.
+
+/// Number of bits set per hash.
+const PROBES: u64 = 3;
+
+pub struct Bloom {
+    words: Vec<u64>,
+    mask: u64,
+}
+
+impl Bloom {
+    /// An empty filter of `bits` bits, rounded up to a power of two (and to at least 64).
+    pub fn new(bits: usize) -> Self {
+        let bits = bits.next_power_of_two().max(64);
+        Self {
+            words: vec![0; bits / 64],
+            mask: bits as u64 - 1,
+        }
+    }
+
+    /// Adds a (uniformly distributed) 64-bit hash, returning whether it was absent, i.e.
+    /// whether one of its bits was not set yet.
+    #[inline(always)]
+    pub fn insert(&mut self, hash: u64) -> bool {
+        // double hashing (Kirsch & Mitzenmacher 2006), with an odd step
+        let step = hash.rotate_left(32) | 1;
+        let mut absent = false;
+        for i in 0..PROBES {
+            let bit = hash.wrapping_add(i.wrapping_mul(step)) & self.mask;
+            let (word, bit) = ((bit / 64) as usize, 1 << (bit % 64));
+            absent |= self.words[word] & bit == 0;
+            self.words[word] |= bit;
+        }
+        absent
+    }
+}
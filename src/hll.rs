@@ -0,0 +1,60 @@
+//! HyperLogLog sketch (Flajolet et al. 2007) estimating the number of distinct hashes,
+//! with the linear counting correction for small cardinalities.
+
+pub struct Hll {
+    precision: u32,
+    registers: Vec<u8>,
+}
+
+impl Hll {
+    /// An empty sketch of `2^precision` registers, with `4 <= precision <= 18`.
+    pub fn new(precision: u32) -> Self {
+        assert!((4..=18).contains(&precision));
+        Self {
+            precision,
+            registers: vec![0; 1 << precision],
+        }
+    }
+
+    /// Adds a (uniformly distributed) 64-bit hash.
+    #[inline(always)]
+    pub fn insert(&mut self, hash: u64) {
+        let index = (hash >> (64 - self.precision)) as usize;
+        // rank of the first 1 bit of the remaining bits, at most 64 - precision + 1
+        let rest = (hash << self.precision) | (1 << (self.precision - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        self.registers[index] = self.registers[index].max(rank);
+    }
+
+    /// Adds all the hashes of `other`, which must have the same precision.
+    pub fn merge(&mut self, other: &Self) {
+        assert_eq!(self.precision, other.precision);
+        for (r, &o) in self.registers.iter_mut().zip(&other.registers) {
+            *r = (*r).max(o);
+        }
+    }
+
+    /// The estimated number of distinct hashes inserted.
+    pub fn estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1. + 1.079 / m),
+        };
+        let sum: f64 = self.registers.iter().map(|&r| (-(r as f64)).exp2()).sum();
+        let raw = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        if raw <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        }
+    }
+
+    /// The standard error of the estimate, relative to the cardinality.
+    pub fn relative_error(&self) -> f64 {
+        1.04 / (self.registers.len() as f64).sqrt()
+    }
+}
@@ -0,0 +1,308 @@
+//! On-disk index of a counted k-mer set, to reuse it without counting again.
+//!
+//! All the fields are little-endian `u64`s: after the magic `DKMINDEX` come the format
+//! version, k, m, whether the k-mers are canonical (0 or 1), the seed of the bucket of each
+//! minimizer, the super-k-mer scheme (0 for minimizers, 1 for syncmers) and the length of
+//! the s-mers of the syncmers (0 for minimizers), the mask of the bases kept by the spaced
+//! seed (0 for contiguous k-mers), the number of shards `n`, and the `n + 1` offsets (in
+//! k-mers) of the shards in the k-mer array that ends the file.
+//! Each shard is sorted, with its k-mers encoded as by `--binary-out`.
+
+use crate::{Scheme, KT};
+use anyhow::{bail, ensure, Context, Result};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 8] = b"DKMINDEX";
+const VERSION: u64 = 3;
+/// Number of `u64` fields of the header, magic included.
+const HEADER_WORDS: usize = 10;
+
+/// Parameters with which the k-mers of an index were counted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub k: usize,
+    pub m: usize,
+    pub canonical: bool,
+    /// the k-mers of a shard depend on it, so only indexes with the same seed can be merged
+    pub seed: u64,
+    /// the shards also depend on how the super-k-mers were cut
+    pub scheme: Scheme,
+    /// with `Scheme::Syncmer`, 0 otherwise
+    pub syncmer_s: usize,
+    /// bit `i` is set if base `i` of each window is kept by the spaced seed, 0 without one
+    pub spaced: u64,
+}
+
+/// Writer of an index, one shard at a time: the offsets are filled in by `finish`.
+pub struct IndexWriter {
+    out: BufWriter<File>,
+    shards: usize,
+    offsets: Vec<u64>,
+}
+
+impl IndexWriter {
+    /// Creates an index of `shards` shards, to be pushed in order.
+    pub fn create(path: &Path, header: &Header, shards: usize) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        let fields = [
+            VERSION,
+            header.k as u64,
+            header.m as u64,
+            header.canonical as u64,
+            header.seed,
+            header.scheme as u64,
+            header.syncmer_s as u64,
+            header.spaced,
+            shards as u64,
+        ];
+        for field in fields {
+            out.write_all(&field.to_le_bytes())?;
+        }
+        // placeholder offsets
+        for _ in 0..=shards {
+            out.write_all(&0u64.to_le_bytes())?;
+        }
+        Ok(Self {
+            out,
+            shards,
+            offsets: vec![0],
+        })
+    }
+
+    /// Appends the sorted k-mers of the next shard.
+    pub fn push_shard(&mut self, kmers: &[KT]) -> io::Result<()> {
+        for &kmer in kmers {
+            self.out.write_all(&kmer.to_le_bytes())?;
+        }
+        let end = self.offsets.last().unwrap() + kmers.len() as u64;
+        self.offsets.push(end);
+        Ok(())
+    }
+
+    /// Writes the offsets of the shards, which must all have been pushed.
+    pub fn finish(mut self) -> io::Result<()> {
+        assert_eq!(self.offsets.len(), self.shards + 1);
+        let offsets_start = (HEADER_WORDS * size_of::<u64>()) as u64;
+        self.out.seek(SeekFrom::Start(offsets_start))?;
+        for offset in &self.offsets {
+            self.out.write_all(&offset.to_le_bytes())?;
+        }
+        self.out.flush()
+    }
+}
+
+/// Whether the file at `path` starts like an index.
+pub fn is_index(path: &Path) -> io::Result<bool> {
+    let mut magic = [0; MAGIC.len()];
+    match File::open(path)?.read_exact(&mut magic) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        result => result.map(|()| &magic == MAGIC),
+    }
+}
+
+/// Reader of an index, loading its shards on demand.
+pub struct IndexReader {
+    file: BufReader<File>,
+    pub header: Header,
+    /// `offsets[i]..offsets[i + 1]` are the k-mers of shard `i`
+    offsets: Vec<u64>,
+}
+
+impl IndexReader {
+    /// Opens an index and reads its header.
+    pub fn open(path: &Path) -> Result<Self> {
+        let context = || format!("Failed to read index {}", path.display());
+        let mut file = BufReader::new(File::open(path).with_context(context)?);
+        let mut words = [0u64; HEADER_WORDS];
+        for word in &mut words {
+            *word = read_u64(&mut file).with_context(context)?;
+        }
+        let [magic, version, k, m, canonical, seed, scheme, syncmer_s, spaced, shards] = words;
+        ensure!(
+            magic.to_le_bytes() == *MAGIC,
+            "{} is not a k-mer index",
+            path.display()
+        );
+        ensure!(
+            version == VERSION,
+            "{} is an index of version {version}, only version {VERSION} is supported",
+            path.display()
+        );
+        let scheme = match scheme {
+            0 => Scheme::Minimizer,
+            1 => Scheme::Syncmer,
+            _ => bail!(
+                "{} has an unknown super-k-mer scheme {scheme}",
+                path.display()
+            ),
+        };
+        let offsets = (0..=shards)
+            .map(|_| read_u64(&mut file))
+            .collect::<io::Result<Vec<_>>>()
+            .with_context(context)?;
+        Ok(Self {
+            file,
+            header: Header {
+                k: k as usize,
+                m: m as usize,
+                canonical: canonical != 0,
+                seed,
+                scheme,
+                syncmer_s: syncmer_s as usize,
+                spaced,
+            },
+            offsets,
+        })
+    }
+
+    /// Ensures that the index holds contiguous k-mers of size `k`, canonical if `canonical`.
+    pub fn check(&self, k: usize, canonical: bool) -> Result<()> {
+        let header = &self.header;
+        ensure!(
+            header.spaced == 0,
+            "The index holds spaced seeds, which cannot be looked up as contiguous k-mers"
+        );
+        ensure!(
+            header.k == k,
+            "The index holds {}-mers, not {k}-mers",
+            header.k
+        );
+        let strands = if header.canonical {
+            "canonical"
+        } else {
+            "forward-only"
+        };
+        ensure!(
+            header.canonical == canonical,
+            "The index holds {strands} k-mers"
+        );
+        Ok(())
+    }
+
+    pub fn shards(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    /// Total number of k-mers.
+    pub fn len(&self) -> u64 {
+        self.offsets[self.shards()]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reads the sorted k-mers of shard `i`.
+    pub fn shard(&mut self, i: usize) -> io::Result<Vec<KT>> {
+        let (start, end) = (self.offsets[i], self.offsets[i + 1]);
+        let data_start = ((HEADER_WORDS + self.offsets.len()) * size_of::<u64>()) as u64;
+        let kmer_bytes = size_of::<KT>() as u64;
+        self.file
+            .seek(SeekFrom::Start(data_start + start * kmer_bytes))?;
+        let mut bytes = vec![0; ((end - start) * kmer_bytes) as usize];
+        self.file.read_exact(&mut bytes)?;
+        let kmers = bytes.chunks_exact(size_of::<KT>());
+        Ok(kmers
+            .map(|b| KT::from_le_bytes(b.try_into().unwrap()))
+            .collect())
+    }
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; size_of::<u64>()];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Unions the indexes at `inputs`, which must have been counted with the same parameters
+/// (the whole header, scheme and spaced seed included, and the same number of shards), into a
+/// new index at `output`, shard by shard. Returns the number of distinct k-mers.
+pub fn merge(inputs: &[PathBuf], output: &Path) -> Result<u64> {
+    let mut readers = inputs
+        .iter()
+        .map(|path| IndexReader::open(path))
+        .collect::<Result<Vec<_>>>()?;
+    ensure!(!readers.is_empty(), "No index to merge");
+    let (header, shards) = (readers[0].header, readers[0].shards());
+    for (reader, path) in readers.iter().zip(inputs) {
+        ensure!(
+            reader.header == header && reader.shards() == shards,
+            "{} was not counted with the same parameters as {}",
+            path.display(),
+            inputs[0].display()
+        );
+    }
+    let context = || format!("Failed to write index {}", output.display());
+    let mut writer = IndexWriter::create(output, &header, shards).with_context(context)?;
+    let mut distinct = 0;
+    for shard in 0..shards {
+        let runs = readers
+            .iter_mut()
+            .zip(inputs)
+            .map(|(reader, path)| {
+                let context = || format!("Failed to read index {}", path.display());
+                reader.shard(shard).with_context(context)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let kmers = merge_runs(&runs);
+        distinct += kmers.len() as u64;
+        writer.push_shard(&kmers).with_context(context)?;
+    }
+    writer.finish().with_context(context)?;
+    Ok(distinct)
+}
+
+/// Merges sorted runs of k-mers into their sorted union, without duplicates.
+fn merge_runs(runs: &[Vec<KT>]) -> Vec<KT> {
+    let mut heads: BinaryHeap<_> = runs
+        .iter()
+        .enumerate()
+        .filter(|(_, run)| !run.is_empty())
+        .map(|(i, run)| Reverse((run[0], i, 0)))
+        .collect();
+    let mut union: Vec<KT> = Vec::with_capacity(runs.iter().map(Vec::len).max().unwrap_or(0));
+    while let Some(Reverse((kmer, i, pos))) = heads.pop() {
+        if union.last() != Some(&kmer) {
+            union.push(kmer);
+        }
+        if let Some(&next) = runs[i].get(pos + 1) {
+            heads.push(Reverse((next, i, pos + 1)));
+        }
+    }
+    union
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spaced_indexes_are_refused() {
+        let name = format!("distinct-kmers-test-{}.idx", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        let header = Header {
+            k: 5,
+            m: 3,
+            canonical: true,
+            seed: 0,
+            scheme: Scheme::Minimizer,
+            syncmer_s: 0,
+            spaced: 0b1101011,
+        };
+        let mut writer = IndexWriter::create(&path, &header, 1).unwrap();
+        writer.push_shard(&[1, 2, 3]).unwrap();
+        writer.finish().unwrap();
+        let index = IndexReader::open(&path);
+        let set = crate::KmerSet::load(&path, 5, true);
+        std::fs::remove_file(&path).unwrap();
+        let index = index.unwrap();
+        assert_eq!(index.header, header);
+        assert!(index.check(5, true).is_err());
+        assert!(set.is_err());
+    }
+}
@@ -0,0 +1,119 @@
+//! Minimal writer for the K-mer File Format (KFF 1.0, https://github.com/Kmer-File-Format/kff-reference).
+//!
+//! The file holds a header declaring our 2-bit encoding, one values section with `k`, `max`
+//! and `data_size`, and one raw section in which every block is a single k-mer, followed by
+//! its count as a big-endian `u32` when counts are written.
+
+use crate::KT;
+use std::io::{self, Write};
+
+/// 2-bit codes of A, C, G, T (in that order, from the high bits) used by `packed_seq`:
+/// A=0, C=1, G=3, T=2.
+const ENCODING: u8 = 0b00_01_11_10;
+/// Blocks hold a single k-mer, but `max = 255` keeps their k-mer count on exactly one byte.
+const MAX_PER_BLOCK: u64 = 255;
+
+/// Writes the `n` k-mers of `kmers` (first base in the low bits) as a KFF file,
+/// along with their counts if `with_counts`.
+pub fn write<W: Write>(
+    mut out: W,
+    k: usize,
+    canonical: bool,
+    n: u64,
+    with_counts: bool,
+    kmers: impl Iterator<Item = (KT, u32)>,
+) -> io::Result<()> {
+    // header: magic, version, encoding, uniqueness, canonicity, empty free block
+    out.write_all(b"KFF")?;
+    out.write_all(&[1, 0, ENCODING, 1, canonical as u8])?;
+    out.write_all(&0u32.to_be_bytes())?;
+
+    let data_size = if with_counts { size_of::<u32>() } else { 0 };
+    write_values(
+        &mut out,
+        &[
+            ("k", k as u64),
+            ("max", MAX_PER_BLOCK),
+            ("data_size", data_size as u64),
+        ],
+    )?;
+
+    out.write_all(b"r")?;
+    out.write_all(&n.to_be_bytes())?;
+    let nbytes = k.div_ceil(4);
+    for (kmer, count) in kmers {
+        out.write_all(&[1])?;
+        // bases from the first one, most significant bits first, left-padded to whole bytes
+        let mut packed: KT = 0;
+        for i in 0..k {
+            packed = (packed << 2) | ((kmer >> (2 * i)) & 3);
+        }
+        out.write_all(&packed.to_be_bytes()[size_of::<KT>() - nbytes..])?;
+        if with_counts {
+            out.write_all(&count.to_be_bytes())?;
+        }
+    }
+
+    out.write_all(b"KFF")?;
+    out.flush()
+}
+
+/// Writes a values section: a list of named `u64`s.
+fn write_values<W: Write>(out: &mut W, values: &[(&str, u64)]) -> io::Result<()> {
+    out.write_all(b"v")?;
+    out.write_all(&(values.len() as u64).to_be_bytes())?;
+    for (name, value) in values {
+        out.write_all(name.as_bytes())?;
+        out.write_all(&[0])?;
+        out.write_all(&value.to_be_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode;
+    use std::fs::File;
+    use std::process::Command;
+
+    #[test]
+    #[ignore = "needs kff-tools (https://github.com/Kmer-File-Format/kff-tools) on the PATH"]
+    fn kff_tools_reads_the_kmers_back() {
+        Command::new("kff-tools")
+            .arg("--help")
+            .output()
+            .expect("kff-tools not in PATH");
+        let k = 7;
+        let kmers: Vec<KT> = vec![0, 1, 0x1b1b, 0x2c4f, 0x3fff];
+        let name = format!("distinct-kmers-test-{}.kff", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        let entries = kmers.iter().map(|&kmer| (kmer, 3));
+        let n = kmers.len() as u64;
+        write(File::create(&path).unwrap(), k, false, n, true, entries).unwrap();
+        let output = Command::new("kff-tools")
+            .args(["outstr", "-i"])
+            .arg(&path)
+            .output()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(output.status.success(), "kff-tools failed: {output:?}");
+        // one `<k-mer> <data>` line per k-mer
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let mut read: Vec<&str> = stdout
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .collect();
+        read.sort_unstable();
+        let mut expected: Vec<String> = kmers
+            .iter()
+            .map(|&kmer| {
+                let mut bases = vec![];
+                decode(kmer, k, &mut bases);
+                String::from_utf8(bases).unwrap()
+            })
+            .collect();
+        expected.sort_unstable();
+        assert_eq!(read, expected);
+    }
+}
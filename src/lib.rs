@@ -0,0 +1,2882 @@
+//! Counting of the distinct k-mers of sequence files.
+//!
+//! The sequences are cut into super-k-mers, which are partitioned by minimizer into
+//! `4^shard_bases` buckets; each bucket is then expanded into its distinct k-mers independently of the others.
+
+use anyhow::{bail, ensure, Context, Result};
+use bloom::Bloom;
+use clap::ValueEnum;
+use hll::Hll;
+use index::IndexReader;
+use indicatif::{ProgressBar, ProgressFinish, ProgressStyle};
+use niffler::send::get_reader;
+use packed_seq::{PackedSeq, PackedSeqVec, Seq, SeqVec};
+use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
+use rayon::{current_num_threads, ThreadPoolBuilder};
+use regex::bytes::{Regex, RegexBuilder};
+use rustc_hash::FxBuildHasher;
+use seq_io::fasta::{self, Record as _};
+use seq_io::fastq::{self, Record as _};
+use seq_io_parallel::{MinimalRefRecord, ParallelProcessor, ParallelReader};
+use simd_minimizers::{
+    canonical_minimizer_and_superkmer_positions, minimizer_and_superkmer_positions,
+};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::mem::take;
+use std::ops::{AddAssign, Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How much is logged to stderr, see [`set_verbosity`].
+static VERBOSITY: AtomicU8 = AtomicU8::new(1);
+
+/// Sets how much [`log!`] writes to stderr: nothing at 0, the progress of the run at 1 (the
+/// default), and the time taken by each file at 2 and by each shard at 3.
+pub fn set_verbosity(level: u8) {
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> u8 {
+    VERBOSITY.load(Ordering::Relaxed)
+}
+
+/// Writes a line to stderr like `eprintln!` if the verbosity is at least `level`.
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => {
+        if $crate::verbosity() >= $level {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+pub mod bloom;
+pub mod hll;
+pub mod index;
+pub mod kff;
+#[cfg(feature = "wide")]
+mod wide;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+
+#[cfg(not(feature = "wide"))]
+pub type KT = u64;
+#[cfg(not(feature = "wide"))]
+pub type SKT = u128; // together as one
+#[cfg(feature = "wide")]
+pub type KT = u128;
+#[cfg(feature = "wide")]
+pub type SKT = wide::U256;
+pub type Bucket = Mutex<Vec<SKT>>;
+
+/// Minimizer bases selecting the bucket of a super-k-mer, unless told otherwise.
+pub const DEFAULT_SHARD_BASES: usize = 8;
+pub const DEFAULT_SYNCMER_S: usize = 11;
+const MAX_SHARD_BASES: usize = 12;
+pub const MAX_K: usize = KT::BITS as usize / 2;
+const SKLEN_BITS: usize = if cfg!(feature = "wide") { 8 } else { 6 };
+const SKLEN_MASK: usize = (1 << SKLEN_BITS) - 1;
+/// Longest super-k-mer that fits in a word along with its length.
+pub const MAX_SKLEN: usize = (SKT::BITS as usize - SKLEN_BITS) / 2;
+const _: () = assert!(MAX_SKLEN <= SKLEN_MASK);
+/// Memory reserved up front for the buckets, in GiB, unless told otherwise.
+pub const DEFAULT_MAX_MEM: f64 = 1.;
+/// Most entries reserved up front by the hash map of a shard, unless told otherwise.
+pub const DEFAULT_MAP_CAPACITY: usize = 1 << 20;
+const LOCAL_CAP: usize = 16; // super-k-mers buffered per shard by each thread
+const CHUNK_BASES: usize = 1 << 31; // sequences are cut to fit their positions in u32
+const WORD_BASES: usize = 29; // longest slice accepted by `PackedSeq::to_word`
+const FORWARD: u8 = 1;
+const REVERSE: u8 = 2;
+
+/// Parameters of the collection phase.
+pub struct CollectParams {
+    pub k: usize,
+    pub m: usize,
+    pub canonical: bool,
+    pub rna: bool,
+    /// the inputs must be FASTQ, rather than detected as FASTQ or FASTA file by file
+    pub is_fastq: bool,
+    pub threads: usize,
+    pub recursive: bool,
+    pub min_length: usize,
+    /// break the sequences at soft-masked (lowercase) bases too
+    pub skip_masked: bool,
+    /// leave out the k-mers overlapping soft-masked bases, without breaking the sequences
+    pub upper_only: bool,
+    /// upper-case the sequences as they are read, so that no base counts as soft-masked
+    pub normalize_case: bool,
+    /// longest chunk of a sequence whose minimizers are computed at once, to bound the memory
+    /// of each thread on long records (the chunks share k - 1 bases, so no k-mer is lost)
+    pub chunk_bases: Option<usize>,
+    /// the reads are interleaved pairs: check that their mates pair up
+    pub interleaved: bool,
+    /// fail, rather than warn, when an input is given twice
+    pub strict: bool,
+    /// in per-record mode, count the k-mers of each window of this many bases (the first
+    /// number), every that many bases (the second one), instead of those of the whole record
+    pub window: Option<(usize, usize)>,
+    /// lowest quality character kept when trimming FASTQ reads
+    pub trim_qual: Option<u8>,
+    /// lowest mean quality character of the FASTQ reads kept, the others being skipped whole
+    pub min_mean_qual: Option<u8>,
+    /// show a progress bar for each input file
+    pub progress: bool,
+    /// super-k-mers reserved per bucket when it is first filled, see [`bucket_cap`]
+    pub bucket_cap: usize,
+    /// exact number of super-k-mers of each bucket, found by [`size_buckets`], reserved
+    /// instead of `bucket_cap`
+    pub bucket_sizes: Option<Vec<usize>>,
+    /// move the buckets that outgrow `bucket_cap` to files in this directory
+    pub spill_dir: Option<PathBuf>,
+    /// the buckets are selected by the first (up to) `shard_bases` bases of the minimizers
+    pub shard_bases: usize,
+    /// keep each record with this probability, deciding by a hash of its id
+    pub subsample: Option<f64>,
+    /// seed of the hashes deciding which records are subsampled and in which bucket each
+    /// minimizer goes (its own value without a seed)
+    pub seed: u64,
+    /// stop after this many records, over all the inputs
+    pub max_records: Option<usize>,
+    /// only count the records whose name (the first word of their header) matches this
+    pub include_contigs: Option<Regex>,
+    /// leave out the records whose name matches this
+    pub exclude_contigs: Option<Regex>,
+    pub scheme: Scheme,
+    /// length of the s-mers defining the closed syncmers, with `Scheme::Syncmer`
+    pub syncmer_s: usize,
+}
+
+/// How the sequences are cut into super-k-mers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Scheme {
+    /// runs of k-mers with the same minimizer of size m
+    Minimizer,
+    /// runs of k-mers with the same smallest closed syncmer of size m
+    Syncmer,
+}
+
+/// A reasonable minimizer size for k-mers of `k` bases: 21 (or k if smaller), for windows
+/// of k - 20 m-mers, but at least `2k - MAX_SKLEN`, so that the longest super-k-mers, of
+/// 2k - m bases, still fit in a word.
+pub fn auto_m(k: usize) -> usize {
+    k.min(21).max((2 * k).saturating_sub(MAX_SKLEN))
+}
+
+/// Initial capacity of each of `shards` buckets so that they reserve `max_mem` GiB in total,
+/// between 16 and 2^20 super-k-mers. The buckets still grow past it as needed.
+pub fn bucket_cap(max_mem: f64, shards: usize) -> usize {
+    let cap = max_mem * (1u64 << 30) as f64 / (shards * size_of::<SKT>()) as f64;
+    (cap as usize).clamp(16, 1 << 20)
+}
+
+impl CollectParams {
+    /// Number of buckets.
+    pub fn shards(&self) -> usize {
+        1 << (2 * self.shard_bases)
+    }
+
+    /// Checks that k and m are supported.
+    pub fn check(&self) -> Result<()> {
+        let CollectParams {
+            k, m, canonical, ..
+        } = *self;
+        ensure!(k <= MAX_K, "k is at most {MAX_K}, see the `wide` feature");
+        ensure!(
+            m <= k,
+            "the minimizers (m = {m}) cannot be longer than the k-mers (k = {k}): lower -m, \
+             or pick it with --auto-m"
+        );
+        ensure!(
+            self.shard_bases <= MAX_SHARD_BASES,
+            "there are at most {MAX_SHARD_BASES} shard bases"
+        );
+        // a super-k-mer holds up to w = k - m + 1 k-mers
+        ensure!(
+            2 * k - m <= MAX_SKLEN,
+            "super-k-mers would be up to {} bases long, more than {MAX_SKLEN}: use a larger m",
+            2 * k - m
+        );
+        // canonical minimizers need an odd window length w + m - 1 = k to break ties
+        ensure!(
+            !canonical || k % 2 == 1 || self.scheme == Scheme::Syncmer,
+            "canonical k-mers need an odd k, use --forward-only (or --scheme syncmer) for even k"
+        );
+        ensure!(
+            self.scheme != Scheme::Syncmer || (1..m).contains(&self.syncmer_s),
+            "syncmers need 0 < s < m"
+        );
+        ensure!(
+            !matches!(self.window, Some((_, 0))),
+            "the windows need a step of at least one base"
+        );
+        if let Some(chunk_bases) = self.chunk_bases {
+            ensure!(
+                (k..=CHUNK_BASES).contains(&chunk_bases),
+                "chunks are between k and {CHUNK_BASES} bases long"
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Number of super-k-mers per minimizer.
+pub type MinimizerCounts = HashMap<KT, usize, FxBuildHasher>;
+/// Shared writer for the per-record counts.
+pub type RecordWriter = Mutex<Box<dyn Write + Send>>;
+/// Receiver of the super-k-mers of `for_each_superkmer`, with the shard of each of them.
+pub type SuperkmerSink<'a> = dyn Fn(usize, SKT) -> io::Result<()> + Sync + 'a;
+
+/// Record tallies of the collection phase.
+#[derive(Default)]
+struct CollectStats {
+    records: AtomicUsize,
+    /// bases of the records, only counted when checking the inputs
+    bases: AtomicUsize,
+    /// with `interleaved`, the reads without a mate number, the first mates and the second
+    /// ones, and the (wrapping) sums of the hashes of their names, equal if they pair up
+    mates: [AtomicUsize; 3],
+    mate_hashes: [AtomicU64; 3],
+    /// records skipped for being shorter than `min_length`
+    short_records: AtomicUsize,
+    /// reads skipped for their mean quality below `min_mean_qual`
+    low_quality: AtomicUsize,
+    /// records left out by the subsampling
+    subsampled_out: AtomicUsize,
+    /// records left out by the name filters
+    filtered_out: AtomicUsize,
+    /// records started, to stop at `max_records`
+    started: AtomicUsize,
+}
+
+impl CollectStats {
+    /// Whether `max_records` records were started.
+    fn reached(&self, max_records: Option<usize>) -> bool {
+        max_records.is_some_and(|max| self.started.load(Ordering::Relaxed) >= max)
+    }
+}
+
+#[derive(Clone)]
+pub struct SuperkmerCollector<'a> {
+    params: &'a CollectParams,
+    buckets: &'a [Bucket],
+    match_n: &'a Regex,
+    match_newline: &'a Regex,
+    /// shared minimizer tally, merged from `local_minimizers` when the collector is dropped
+    minimizers: Option<&'a Mutex<MinimizerCounts>>,
+    local_minimizers: MinimizerCounts,
+    /// when set, each record is counted on its own instead of filling the buckets
+    per_record: Option<&'a RecordWriter>,
+    stats: &'a CollectStats,
+    /// records seen by this collector, added to `stats` when it is dropped
+    local_records: usize,
+    /// when set, the records are only read and their bases counted, into `local_bases`
+    check_only: bool,
+    local_bases: usize,
+    local_mates: [usize; 3],
+    local_mate_hashes: [u64; 3],
+    record_superkmers: Vec<SKT>,
+    record_kmers: HashSet<KT, FxBuildHasher>,
+    /// with `window`, the k-mers of the current record and their positions, and the
+    /// multiplicities of those of the current window
+    window_kmers: Vec<(usize, KT)>,
+    window_counts: HashMap<KT, u32, FxBuildHasher>,
+    /// super-k-mers waiting to be moved to `buckets`, `LOCAL_CAP` at a time, to lock less
+    local_buckets: Vec<Vec<SKT>>,
+    /// when set, the super-k-mers of each bucket are only counted, into `local_sizes` and
+    /// then into `sizes` when the collector is dropped
+    sizes: Option<&'a [AtomicUsize]>,
+    local_sizes: Vec<usize>,
+    /// when set, the super-k-mers are handed to it instead of filling the buckets
+    sink: Option<&'a SuperkmerSink<'a>>,
+    /// whether the file being read is FASTQ, so that its reads can be trimmed
+    is_fastq: bool,
+    min_pos_vec: Vec<u32>,
+    sk_pos_vec: Vec<u32>,
+    scratch: Vec<u8>,
+    /// upper-cased copy of the current sequence, with `normalize_case`
+    upper: Vec<u8>,
+    /// 2-bit bases, hashes of the s-mers and m-mers, and ranks of the m-mers for
+    /// `Scheme::Syncmer`
+    bases: Vec<u8>,
+    smer_hashes: Vec<u64>,
+    mmer_hashes: Vec<u64>,
+    mmer_ranks: Vec<(bool, u64)>,
+    /// number of soft-masked bases before each position of the current sequence, with
+    /// `upper_only`
+    masked: Vec<u32>,
+    /// the unmasked runs of bases of the current super-k-mer, with `upper_only`
+    runs: Vec<Range<usize>>,
+}
+
+impl<'a> SuperkmerCollector<'a> {
+    fn new(
+        params: &'a CollectParams,
+        buckets: &'a [Bucket],
+        match_n: &'a Regex,
+        match_newline: &'a Regex,
+        minimizers: Option<&'a Mutex<MinimizerCounts>>,
+        per_record: Option<&'a RecordWriter>,
+        stats: &'a CollectStats,
+    ) -> Self {
+        Self {
+            params,
+            buckets,
+            match_n,
+            match_newline,
+            minimizers,
+            local_minimizers: MinimizerCounts::default(),
+            per_record,
+            stats,
+            local_records: 0,
+            check_only: false,
+            local_bases: 0,
+            local_mates: [0; 3],
+            local_mate_hashes: [0; 3],
+            record_superkmers: vec![],
+            record_kmers: HashSet::default(),
+            window_kmers: vec![],
+            window_counts: HashMap::default(),
+            local_buckets: vec![],
+            sizes: None,
+            local_sizes: vec![],
+            sink: None,
+            is_fastq: false,
+            min_pos_vec: vec![],
+            sk_pos_vec: vec![],
+            scratch: vec![],
+            upper: vec![],
+            bases: vec![],
+            smer_hashes: vec![],
+            mmer_hashes: vec![],
+            mmer_ranks: vec![],
+            masked: vec![],
+            runs: vec![],
+        }
+    }
+
+    /// Appends a line of the current segment to `packed_seq`, tracking its lowercase bases.
+    fn push_line(&mut self, packed_seq: &mut PackedSeqVec, line: &[u8]) {
+        if self.params.upper_only {
+            for &c in line {
+                let masked = *self.masked.last().unwrap();
+                self.masked.push(masked + c.is_ascii_lowercase() as u32);
+            }
+        }
+        if self.params.rna {
+            self.scratch.clear();
+            self.scratch.extend(line.iter().map(|&c| match c {
+                b'U' => b'T',
+                b'u' => b't',
+                c => c,
+            }));
+            packed_seq.push_ascii(&self.scratch);
+        } else {
+            packed_seq.push_ascii(line);
+        }
+    }
+
+    /// Collects the super-k-mers of a sequence of at least k bases and less than 2^32,
+    /// starting at `offset` in the current sequence.
+    fn collect_chunk(&mut self, seq: PackedSeq, offset: usize) -> io::Result<()> {
+        if self.params.scheme == Scheme::Syncmer {
+            return self.collect_syncmer_chunk(seq, offset);
+        }
+        let CollectParams {
+            k, m, canonical, ..
+        } = *self.params;
+        let w = k - m + 1;
+        let len = seq.len();
+        self.min_pos_vec.clear();
+        self.min_pos_vec.reserve(len * 5 / 2 / (w + 1));
+        self.sk_pos_vec.clear();
+        self.sk_pos_vec.reserve(len * 5 / 2 / (w + 1));
+        if canonical {
+            canonical_minimizer_and_superkmer_positions(
+                seq,
+                m,
+                w,
+                &mut self.min_pos_vec,
+                &mut self.sk_pos_vec,
+            );
+        } else {
+            minimizer_and_superkmer_positions(
+                seq,
+                m,
+                w,
+                &mut self.min_pos_vec,
+                &mut self.sk_pos_vec,
+            );
+        }
+        self.min_pos_vec.push(u32::MAX);
+        self.sk_pos_vec.push((len - (k - 1)) as u32);
+        // taken out of `self` while the super-k-mers are pushed, and put back for the next chunk
+        let (min_pos_vec, sk_pos_vec) = (take(&mut self.min_pos_vec), take(&mut self.sk_pos_vec));
+        let mut min_pos = min_pos_vec[0];
+        let mut sk_pos = sk_pos_vec[0];
+        for (&next_min_pos, &next_sk_pos) in min_pos_vec.iter().zip(&sk_pos_vec).skip(1) {
+            let min_range = (min_pos as usize)..(min_pos as usize + m);
+            let mmer = low(to_word(seq, min_range));
+            // both strands of a region must land in the same shard
+            let minimizer = if canonical {
+                mmer.min(revcomp(mmer, m))
+            } else {
+                mmer
+            };
+            // the first (up to) shard_bases bases of the minimizer, never past its end,
+            // or of its seeded hash
+            let key = match self.params.seed {
+                0 => minimizer as usize,
+                seed => hash_kmer(minimizer, seed) as usize,
+            };
+            let sk_range = (sk_pos as usize)..((next_sk_pos as usize) + k - 1);
+            self.push_superkmer(seq, offset, sk_range, minimizer, key)?;
+            min_pos = next_min_pos;
+            sk_pos = next_sk_pos;
+        }
+        (self.min_pos_vec, self.sk_pos_vec) = (min_pos_vec, sk_pos_vec);
+        Ok(())
+    }
+
+    /// Collects the super-k-mers of a sequence of at least k bases with `Scheme::Syncmer`:
+    /// the representative of a k-mer is its smallest m-mer by hash among the closed syncmers
+    /// (m-mers whose smallest s-mer is at one of their ends), or among all its m-mers if none
+    /// is a syncmer, and the super-k-mers are the runs of k-mers with the same representative.
+    /// It only depends on the (canonical) k-mer, so both schemes give the same counts.
+    fn collect_syncmer_chunk(&mut self, seq: PackedSeq, offset: usize) -> io::Result<()> {
+        let CollectParams {
+            k,
+            m,
+            canonical,
+            syncmer_s: s,
+            seed,
+            ..
+        } = *self.params;
+        let w = k - m + 1;
+        self.bases.clear();
+        self.bases.extend(seq.iter_bp());
+        hash_kmers(&self.bases, s, canonical, seed, &mut self.smer_hashes);
+        hash_kmers(&self.bases, m, canonical, seed, &mut self.mmer_hashes);
+        let mut mmer_ranks = take(&mut self.mmer_ranks);
+        mmer_ranks.clear();
+        for (i, &hash) in self.mmer_hashes.iter().enumerate() {
+            // by value rather than position, so that both strands agree
+            let smers = &self.smer_hashes[i..i + m - s + 1];
+            let min = *smers.iter().min().unwrap();
+            let closed = smers[0] == min || smers[m - s] == min;
+            // syncmers first
+            mmer_ranks.push((!closed, hash));
+        }
+        let mut start = 0;
+        let mut rep = *mmer_ranks[0..w].iter().min().unwrap();
+        let kmers = seq.len() - k + 1;
+        for i in 1..=kmers {
+            let next = (i < kmers).then(|| *mmer_ranks[i..i + w].iter().min().unwrap());
+            // a repeated representative could make a super-k-mer too long
+            if next != Some(rep) || i - start == w {
+                let (_, hash) = rep;
+                let sk_range = start..i + k - 1;
+                self.push_superkmer(seq, offset, sk_range, hash as KT, hash as usize)?;
+                start = i;
+                rep = next.unwrap_or(rep);
+            }
+        }
+        self.mmer_ranks = mmer_ranks;
+        Ok(())
+    }
+
+    /// Adds a super-k-mer of a chunk starting at `offset` to the bucket selected by the low
+    /// bits of `key`, or to those of the current record in per-record mode. With
+    /// `upper_only`, only its runs of k-mers without soft-masked bases are added.
+    fn push_superkmer(
+        &mut self,
+        seq: PackedSeq,
+        offset: usize,
+        sk_range: Range<usize>,
+        minimizer: KT,
+        key: usize,
+    ) -> io::Result<()> {
+        if self.minimizers.is_some() {
+            *self.local_minimizers.entry(minimizer).or_default() += 1;
+        }
+        if !self.params.upper_only {
+            return self.push_run(seq, sk_range, key);
+        }
+        let k = self.params.k;
+        let masked = &self.masked[offset..];
+        let unmasked = |i: usize| masked[i + k] == masked[i];
+        // taken out of `self` while the runs are pushed, and put back for the next super-k-mer
+        let mut runs = take(&mut self.runs);
+        runs.clear();
+        let mut i = sk_range.start;
+        while i + k <= sk_range.end {
+            let start = i;
+            while i + k <= sk_range.end && unmasked(i) {
+                i += 1;
+            }
+            if i > start {
+                runs.push(start..i - 1 + k);
+            }
+            i += 1;
+        }
+        for run in &runs {
+            self.push_run(seq, run.clone(), key)?;
+        }
+        self.runs = runs;
+        Ok(())
+    }
+
+    /// Adds the bases of `sk_range` as one super-k-mer to the bucket selected by `key`.
+    fn push_run(&mut self, seq: PackedSeq, sk_range: Range<usize>, key: usize) -> io::Result<()> {
+        let shard = key & (self.buckets.len() - 1);
+        // little-endian order, length in the low bits
+        let sklen = SKT::from(sk_range.len() as u64);
+        let skmer = (to_word(seq, sk_range) << SKLEN_BITS) | sklen;
+        if self.per_record.is_some() {
+            self.record_superkmers.push(skmer);
+            return Ok(());
+        }
+        if self.sizes.is_some() {
+            if self.local_sizes.is_empty() {
+                self.local_sizes.resize(self.buckets.len(), 0);
+            }
+            self.local_sizes[shard] += 1;
+            return Ok(());
+        }
+        if let Some(sink) = self.sink {
+            return sink(shard, skmer);
+        }
+        if self.local_buckets.is_empty() {
+            self.local_buckets.resize_with(self.buckets.len(), Vec::new);
+        }
+        let local = &mut self.local_buckets[shard];
+        local.push(skmer);
+        if local.len() >= LOCAL_CAP {
+            let mut bucket = self.buckets[shard].lock().unwrap();
+            reserve(&mut bucket, self.params, shard);
+            bucket.append(local);
+            if let Some(dir) = &self.params.spill_dir {
+                if bucket.len() >= self.params.bucket_cap {
+                    spill(dir, shard, &mut bucket)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SuperkmerCollector<'_> {
+    /// Writes `<id>\t<start>\t<end>\t<distinct k-mers>` lines to `out` for the windows of
+    /// `window` bases of `seq` starting every `step` bases, each with the k-mers lying entirely
+    /// inside it. The k-mers are read straight from the sequence, breaking at the same bases.
+    fn write_windows(
+        &mut self,
+        id: &[u8],
+        seq: &[u8],
+        (window, step): (usize, usize),
+        out: &RecordWriter,
+    ) -> io::Result<()> {
+        let CollectParams { k, canonical, .. } = *self.params;
+        let kmer_mask = KT::MAX >> (KT::BITS as usize - 2 * k);
+        let (mut kmer, mut rc, mut run): (KT, KT, usize) = (0, 0, 0);
+        let mut pos = 0;
+        self.window_kmers.clear();
+        for &c in seq {
+            if c == b'\n' || c == b'\r' {
+                continue;
+            }
+            pos += 1;
+            let masked = self.params.skip_masked && c.is_ascii_lowercase();
+            if masked || !matches!(c.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T' | b'U') {
+                run = 0;
+                continue;
+            }
+            // same 2-bit code as `packed_seq`, first base in the low bits; complement is ^2
+            let code = ((c >> 1) & 3) as KT;
+            kmer = (kmer >> 2) | (code << (2 * (k - 1)));
+            rc = ((rc << 2) | (code ^ 2)) & kmer_mask;
+            run += 1;
+            if run >= k {
+                let kmer = if canonical { kmer.min(rc) } else { kmer };
+                self.window_kmers.push((pos - k, kmer));
+            }
+        }
+        let len = pos;
+        // the k-mers of [start, end) are those of window_kmers[first..last]
+        let (mut first, mut last) = (0, 0);
+        let mut lines = Vec::new();
+        self.window_counts.clear();
+        for start in (0..len).step_by(step) {
+            let end = (start + window).min(len);
+            while let Some(&(pos, kmer)) = self.window_kmers.get(last) {
+                if pos + k > end {
+                    break;
+                }
+                *self.window_counts.entry(kmer).or_default() += 1;
+                last += 1;
+            }
+            while let Some(&(pos, kmer)) = self.window_kmers[..last].get(first) {
+                if pos >= start {
+                    break;
+                }
+                let count = self.window_counts.get_mut(&kmer).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    self.window_counts.remove(&kmer);
+                }
+                first += 1;
+            }
+            lines.extend_from_slice(id);
+            writeln!(lines, "\t{start}\t{end}\t{}", self.window_counts.len())?;
+            if end == len {
+                break;
+            }
+        }
+        out.lock().unwrap().write_all(&lines)
+    }
+}
+
+impl ParallelProcessor for SuperkmerCollector<'_> {
+    fn process_record<'a, Rf: MinimalRefRecord<'a>>(&mut self, record: Rf) -> Result<()> {
+        let CollectParams { k, canonical, .. } = *self.params;
+        if let Some(max_records) = self.params.max_records {
+            // the file is still read to its end (skipping the records), but no other file is
+            if self.stats.started.fetch_add(1, Ordering::Relaxed) >= max_records {
+                return Ok(());
+            }
+        }
+        self.local_records += 1;
+        if self.params.interleaved {
+            let (mate, name) = mate_of(record.ref_head()).unwrap_or((0, b""));
+            self.local_mates[mate] += 1;
+            let hash = &mut self.local_mate_hashes[mate];
+            *hash = hash.wrapping_add(hash_id(name, 0));
+        }
+        if self.check_only {
+            let seq = record.ref_seq();
+            let newlines = seq.iter().filter(|&&c| c == b'\n' || c == b'\r').count();
+            self.local_bases += seq.len() - newlines;
+            return Ok(());
+        }
+        let head = record.ref_head();
+        let id = head.split(u8::is_ascii_whitespace).next().unwrap_or(head);
+        if let Some(fraction) = self.params.subsample {
+            if hash_id(id, self.params.seed) as f64 >= fraction * u64::MAX as f64 {
+                self.stats.subsampled_out.fetch_add(1, Ordering::Relaxed);
+                return Ok(());
+            }
+        }
+        let included = match &self.params.include_contigs {
+            Some(include) => include.is_match(id),
+            None => true,
+        };
+        let excluded = match &self.params.exclude_contigs {
+            Some(exclude) => exclude.is_match(id),
+            None => false,
+        };
+        if !included || excluded {
+            self.stats.filtered_out.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+        let min_length = self.params.min_length;
+        if min_length > 0 {
+            let seq = record.ref_seq();
+            let newlines = seq.iter().filter(|&&c| c == b'\n' || c == b'\r').count();
+            if seq.len() - newlines < min_length {
+                self.stats.short_records.fetch_add(1, Ordering::Relaxed);
+                return Ok(());
+            }
+        }
+        if let Some(min_qual) = self.params.min_mean_qual.filter(|_| self.is_fastq) {
+            let qual = record.ref_qual();
+            let sum: usize = qual.iter().map(|&q| q as usize).sum();
+            if sum < min_qual as usize * qual.len() {
+                self.stats.low_quality.fetch_add(1, Ordering::Relaxed);
+                return Ok(());
+            }
+        }
+        self.record_superkmers.clear();
+        let seq = match self.params.trim_qual {
+            Some(min_qual) if self.is_fastq => {
+                trim_quality(record.ref_seq(), record.ref_qual(), min_qual)
+            }
+            _ => record.ref_seq(),
+        };
+        // the packing itself takes both cases, so this only matters to the masking options
+        let mut upper = take(&mut self.upper);
+        let seq = if self.params.normalize_case {
+            upper.clear();
+            upper.extend(seq.iter().map(u8::to_ascii_uppercase));
+            &upper[..]
+        } else {
+            seq
+        };
+        if let (Some(out), Some(window)) = (self.per_record, self.params.window) {
+            self.write_windows(id, seq, window, out)?;
+            self.upper = upper;
+            return Ok(());
+        }
+        // each segment between two breaks is packed and minimized on its own: no k-mer
+        // straddles a break, but those ending right before it or starting right after it are
+        // counted, the minimizer windows restarting at the first base of every segment
+        for raw_seq in self.match_n.split(seq).filter(|&s| s.len() >= k) {
+            let mut packed_seq = PackedSeqVec::default();
+            self.masked.clear();
+            self.masked.push(0);
+            // single-line sequences (all those of FASTQ files) are pushed without the split
+            if raw_seq.iter().any(|&c| c == b'\n' || c == b'\r') {
+                for line in self.match_newline.split(raw_seq).filter(|l| !l.is_empty()) {
+                    self.push_line(&mut packed_seq, line);
+                }
+            } else {
+                self.push_line(&mut packed_seq, raw_seq);
+            }
+
+            // the newlines were counted in the length of the raw sequence
+            let len = packed_seq.len();
+            if len < k {
+                continue;
+            }
+            // positions are u32, so huge sequences are cut into chunks sharing k - 1 bases
+            let chunk_bases = self.params.chunk_bases.unwrap_or(CHUNK_BASES);
+            for start in (0..len - (k - 1)).step_by(chunk_bases - (k - 1)) {
+                let end = (start + chunk_bases).min(len);
+                self.collect_chunk(packed_seq.as_slice().slice(start..end), start)?;
+            }
+        }
+        self.upper = upper;
+        if let Some(out) = self.per_record {
+            self.record_kmers.clear();
+            for &skmer in &self.record_superkmers {
+                for_each_kmer(skmer, k, canonical, |kmer, _| {
+                    self.record_kmers.insert(kmer);
+                });
+            }
+            let head = record.ref_head();
+            let id = head.split(u8::is_ascii_whitespace).next().unwrap_or(head);
+            let mut line = id.to_vec();
+            writeln!(line, "\t{}", self.record_kmers.len())?;
+            out.lock().unwrap().write_all(&line)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SuperkmerCollector<'_> {
+    fn drop(&mut self) {
+        for (shard, local) in self.local_buckets.iter_mut().enumerate() {
+            if !local.is_empty() {
+                let mut bucket = self.buckets[shard].lock().unwrap();
+                reserve(&mut bucket, self.params, shard);
+                bucket.append(local);
+            }
+        }
+        if let Some(sizes) = self.sizes {
+            for (size, &local) in sizes.iter().zip(&self.local_sizes) {
+                size.fetch_add(local, Ordering::Relaxed);
+            }
+        }
+        let records = take(&mut self.local_records);
+        self.stats.records.fetch_add(records, Ordering::Relaxed);
+        let bases = take(&mut self.local_bases);
+        self.stats.bases.fetch_add(bases, Ordering::Relaxed);
+        for mate in 0..3 {
+            let n = take(&mut self.local_mates[mate]);
+            self.stats.mates[mate].fetch_add(n, Ordering::Relaxed);
+            let hash = take(&mut self.local_mate_hashes[mate]);
+            self.stats.mate_hashes[mate].fetch_add(hash, Ordering::Relaxed);
+        }
+        if let Some(minimizers) = self.minimizers {
+            let mut minimizers = minimizers.lock().unwrap();
+            for (minimizer, n) in self.local_minimizers.drain() {
+                *minimizers.entry(minimizer).or_default() += n;
+            }
+        }
+    }
+}
+
+/// Reserves the capacity of a bucket when its first super-k-mers arrive, so that the buckets
+/// left empty (all of them for an empty or all-N input) take no memory.
+fn reserve(bucket: &mut Vec<SKT>, params: &CollectParams, shard: usize) {
+    if bucket.capacity() == 0 {
+        let cap = match &params.bucket_sizes {
+            Some(sizes) => sizes[shard],
+            None => params.bucket_cap,
+        };
+        bucket.reserve_exact(cap);
+    }
+}
+
+/// Mate number (1 or 2) of a read of an interleaved file and its name without it, from a
+/// `/1` or `/2` suffix of its id or from a `1:` or `2:` comment (as written by Casava 1.8).
+fn mate_of(head: &[u8]) -> Option<(usize, &[u8])> {
+    let mut words = head.split(u8::is_ascii_whitespace);
+    let id = words.next()?;
+    match id {
+        [name @ .., b'/', mate @ (b'1' | b'2')] => Some(((mate - b'0') as usize, name)),
+        _ => match words.next()? {
+            [mate @ (b'1' | b'2'), b':', ..] => Some(((mate - b'0') as usize, id)),
+            _ => None,
+        },
+    }
+}
+
+/// Reports the complete pairs of interleaved inputs, warning when their reads do not pair up.
+fn report_pairs(stats: &CollectStats) {
+    let [unnumbered, first, second] = stats.mates.each_ref().map(|n| n.load(Ordering::Relaxed));
+    let [_, first_names, second_names] = stats
+        .mate_hashes
+        .each_ref()
+        .map(|hash| hash.load(Ordering::Relaxed));
+    if unnumbered == 0 && first == second && first_names == second_names {
+        log!(1, "Read {first} complete pairs");
+    } else {
+        eprintln!(
+            "Warning: the interleaved reads do not pair up ({first} first mates, {second} \
+             second mates, {unnumbered} reads without a mate number), is an input truncated?"
+        );
+    }
+}
+
+/// Removes the bases of quality below `min_qual` from both ends of `seq`.
+fn trim_quality<'s>(seq: &'s [u8], qual: &[u8], min_qual: u8) -> &'s [u8] {
+    let Some(start) = qual.iter().position(|&q| q >= min_qual) else {
+        return &[];
+    };
+    let end = qual.iter().rposition(|&q| q >= min_qual).unwrap() + 1;
+    &seq[start..end]
+}
+
+/// Packs `seq[range]` into a word (first base in the low bits), in slices accepted by `to_word`.
+fn to_word(seq: PackedSeq, range: Range<usize>) -> SKT {
+    let mut word = SKT::default();
+    for start in range.clone().step_by(WORD_BASES) {
+        let end = (start + WORD_BASES).min(range.end);
+        word |= SKT::from(seq.slice(start..end).to_word() as u64) << (2 * (start - range.start));
+    }
+    word
+}
+
+/// The low bits of a word, as a k-mer.
+#[cfg(not(feature = "wide"))]
+fn low(word: SKT) -> KT {
+    word as KT
+}
+
+/// The low bits of a word, as a k-mer.
+#[cfg(feature = "wide")]
+fn low(word: SKT) -> KT {
+    word.low()
+}
+
+/// Reverse complement of a k-mer in the little-endian 2-bit encoding of `packed_seq`
+/// (A=0, C=1, T=2, G=3, so complementing a base flips its high bit).
+fn revcomp(kmer: KT, k: usize) -> KT {
+    // complement, then reverse the bits and swap them back within each base
+    let x = (kmer ^ (KT::MAX / 3 * 2)).reverse_bits();
+    (((x >> 1) & (KT::MAX / 3)) | ((x & (KT::MAX / 3)) << 1)) >> (KT::BITS as usize - 2 * k)
+}
+
+/// Replaces `out` with the hashes of the (canonical if `canonical`) k-mers of a sequence of
+/// 2-bit bases.
+fn hash_kmers(bases: &[u8], k: usize, canonical: bool, seed: u64, out: &mut Vec<u64>) {
+    let kmer_mask = KT::MAX >> (KT::BITS as usize - 2 * k);
+    let (mut kmer, mut rc): (KT, KT) = (0, 0);
+    out.clear();
+    for (i, &base) in bases.iter().enumerate() {
+        // first base in the low bits; the complement of a base is `base ^ 2`
+        kmer = (kmer >> 2) | ((base as KT) << (2 * (k - 1)));
+        rc = ((rc << 2) | (base as KT ^ 2)) & kmer_mask;
+        if i + 1 >= k {
+            let kmer = if canonical { kmer.min(rc) } else { kmer };
+            out.push(hash_kmer(kmer, seed));
+        }
+    }
+}
+
+/// What is remembered about each distinct k-mer of a shard.
+trait KmerInfo: Default {
+    /// Whether `count` is tracked.
+    const COUNTS: bool = false;
+    /// Whether `strands` is tracked.
+    const STRANDS: bool = false;
+    /// Records one occurrence; `strand` is `FORWARD` and/or `REVERSE`.
+    fn observe(&mut self, strand: u8);
+    /// The number of occurrences of the k-mer.
+    fn count(&self) -> u32 {
+        1
+    }
+    /// The strands on which the k-mer was seen.
+    fn strands(&self) -> u8 {
+        FORWARD
+    }
+}
+
+/// Plain distinct counting.
+impl KmerInfo for () {
+    #[inline(always)]
+    fn observe(&mut self, _strand: u8) {}
+}
+
+/// The strands on which the canonical k-mer was seen.
+impl KmerInfo for u8 {
+    const STRANDS: bool = true;
+    #[inline(always)]
+    fn observe(&mut self, strand: u8) {
+        *self |= strand;
+    }
+    fn strands(&self) -> u8 {
+        *self
+    }
+}
+
+/// The multiplicity of the k-mer, saturating at `u32::MAX`.
+impl KmerInfo for u32 {
+    const COUNTS: bool = true;
+    #[inline(always)]
+    fn observe(&mut self, _strand: u8) {
+        *self = self.saturating_add(1);
+    }
+    fn count(&self) -> u32 {
+        *self
+    }
+}
+
+/// Both the multiplicity and the strands.
+impl KmerInfo for (u32, u8) {
+    const COUNTS: bool = true;
+    const STRANDS: bool = true;
+    #[inline(always)]
+    fn observe(&mut self, strand: u8) {
+        self.0.observe(strand);
+        self.1.observe(strand);
+    }
+    fn count(&self) -> u32 {
+        self.0
+    }
+    fn strands(&self) -> u8 {
+        self.1
+    }
+}
+
+/// Parameters of the counting phase.
+#[derive(Clone)]
+pub struct CountParams {
+    pub k: usize,
+    pub w: usize,
+    pub canonical: bool,
+    /// keep the distinct k-mers of each shard to write them out
+    pub keep_kmers: bool,
+    /// sort the kept k-mers of each shard
+    pub sort_kmers: bool,
+    /// last bin of the abundance histogram, if it is computed
+    pub histo_max: Option<usize>,
+    /// only k-mers occurring at least this many times are solid
+    pub min_count: Option<u32>,
+    /// only k-mers occurring at most this many times are solid
+    pub max_count: Option<u32>,
+    /// only count the k-mers whose hash is at most `u64::MAX / scaled` (FracMinHash)
+    pub scaled: Option<u64>,
+    /// track the multiplicity of each k-mer
+    pub counts: bool,
+    /// track the strands on which each canonical k-mer was seen
+    pub strands: bool,
+    /// directory where the buckets were spilled, if they were
+    pub spill_dir: Option<PathBuf>,
+    pub method: CountMethod,
+    /// most entries reserved up front by the hash map of a shard, which still grows as needed
+    pub map_capacity: usize,
+    /// keep the `n` smallest k-mer hashes of each shard (bottom-n MinHash)
+    pub minhash: Option<usize>,
+    /// seed of the k-mer hash used by `scaled` and `minhash`
+    pub seed: u64,
+    /// with a spaced seed, the positions of the bases kept from each window of `k` bases
+    pub spaced: Option<Vec<usize>>,
+    /// only count the k-mers with a number of G and C bases in this range
+    pub gc: Option<RangeInclusive<u32>>,
+    /// only count the k-mers whose base composition has at least this entropy, in bits
+    pub min_entropy: Option<f64>,
+    /// also count the distinct super-k-mers of each shard
+    pub distinct_superkmers: bool,
+    /// also count the distinct k-mers that are their own reverse complement
+    pub palindromes: bool,
+    /// also tally the distinct k-mers by number of G and C bases
+    pub gc_histogram: bool,
+    /// sort the super-k-mers of each shard before counting them, so that its k-mers come out
+    /// in the same order from run to run
+    pub stable: bool,
+    /// count at most this many shards at once, in a pool of that many threads, so that fewer
+    /// maps are in memory together
+    pub concurrent_shards: Option<usize>,
+}
+
+/// How the distinct k-mers of a shard are found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CountMethod {
+    /// insert them in a hash map
+    Hash,
+    /// sort them all, then group the equal ones: faster when most of them are distinct
+    Sort,
+}
+
+impl CountParams {
+    fn below_min(&self, count: u32) -> bool {
+        self.min_count.is_some_and(|min| count < min)
+    }
+
+    fn above_max(&self, count: u32) -> bool {
+        self.max_count.is_some_and(|max| count > max)
+    }
+
+    /// Whether a k-mer occurring `count` times passes the abundance filters.
+    fn is_solid(&self, count: u32) -> bool {
+        !self.below_min(count) && !self.above_max(count)
+    }
+}
+
+/// Per-shard results of the counting phase.
+#[derive(Default)]
+pub struct ShardCount {
+    pub superkmers: usize,
+    /// k-mers with multiplicity
+    pub total: usize,
+    pub distinct: usize,
+    /// distinct k-mers passing the abundance filters
+    pub solid: usize,
+    /// distinct k-mers occurring once, when counting
+    pub singletons: usize,
+    /// distinct k-mers removed by the lower abundance bound
+    pub below_min: usize,
+    /// distinct k-mers removed by the upper abundance bound
+    pub above_max: usize,
+    /// distinct k-mers seen on the forward strand only, the reverse strand only, and both
+    pub strands: [usize; 3],
+    /// the distinct k-mers themselves, when they are written out
+    pub kmers: Vec<KT>,
+    /// the multiplicity of each of `kmers`, when counting
+    pub counts: Vec<u32>,
+    /// number of distinct k-mers per multiplicity, only as long as needed by the shard
+    pub histogram: Vec<usize>,
+    /// occurrences of the k-mers of the last (capped) histogram bin
+    pub histogram_tail: usize,
+    /// the smallest hashes of the distinct k-mers, ascending, when sketching
+    pub minhash: Vec<u64>,
+    /// k-mers with multiplicity removed by the GC content filter
+    pub gc_filtered: usize,
+    /// k-mers with multiplicity removed by the entropy filter
+    pub low_entropy: usize,
+    /// distinct super-k-mers, when they are counted
+    pub distinct_superkmers: usize,
+    /// distinct k-mers that are their own reverse complement, when they are counted
+    pub palindromes: usize,
+    /// number of distinct k-mers per number of G and C bases, when they are tallied
+    pub gc_histogram: Vec<usize>,
+}
+
+impl ShardCount {
+    /// Counts the k-mers of a bucket, remembering `I` about each of them.
+    fn new<I: KmerInfo>(mut v: Vec<SKT>, params: &CountParams) -> Self {
+        let superkmers = v.len();
+        // the buckets are filled in whatever order the threads push to them, and the order in
+        // which the k-mers come out of a map depends on the order they went in
+        if params.stable {
+            v.sort_unstable();
+        }
+        let distinct_superkmers = if params.distinct_superkmers {
+            count_distinct_superkmers(&mut v)
+        } else {
+            0
+        };
+        let total = kmers_in(&v, params.k);
+        let (shard, filtered) = match params.method {
+            CountMethod::Hash => {
+                let (map, filtered) = count_shard::<I>(v, params);
+                let shard = Self::from_entries(superkmers, total, map, params);
+                (shard, filtered)
+            }
+            // the sorted k-mers are only grouped, the entries come out sorted
+            CountMethod::Sort if I::STRANDS => {
+                let mut kmers = Vec::with_capacity(total / params.scaled.unwrap_or(1) as usize);
+                let filtered =
+                    for_each_sampled_kmer(v, params, |kmer, strand| kmers.push((kmer, strand)));
+                kmers.sort_unstable_by_key(|&(kmer, _)| kmer);
+                let entries = runs::<I>(kmers.into_iter());
+                let shard = Self::from_entries(superkmers, total, entries, params);
+                (shard, filtered)
+            }
+            CountMethod::Sort => {
+                let mut kmers = Vec::with_capacity(total / params.scaled.unwrap_or(1) as usize);
+                let filtered = for_each_sampled_kmer(v, params, |kmer, _| kmers.push(kmer));
+                kmers.sort_unstable();
+                let kmers = kmers.into_iter().map(|kmer| (kmer, FORWARD));
+                let shard = Self::from_entries(superkmers, total, runs::<I>(kmers), params);
+                (shard, filtered)
+            }
+        };
+        Self {
+            gc_filtered: filtered.gc,
+            low_entropy: filtered.low_entropy,
+            distinct_superkmers,
+            ..shard
+        }
+    }
+
+    /// Tallies the distinct k-mers of a shard along with what is known about each of them.
+    fn from_entries<I: KmerInfo>(
+        superkmers: usize,
+        total: usize,
+        entries: impl IntoIterator<Item = (KT, I)>,
+        params: &CountParams,
+    ) -> Self {
+        let mut shard = Self {
+            superkmers,
+            total,
+            ..Default::default()
+        };
+        if params.gc_histogram {
+            shard.gc_histogram = vec![0; params.k + 1];
+        }
+        // max-heap of the smallest hashes so far
+        let mut minhash = BinaryHeap::with_capacity(params.minhash.unwrap_or(0) + 1);
+        for (kmer, info) in entries {
+            shard.distinct += 1;
+            if params.palindromes {
+                shard.palindromes += (kmer == revcomp(kmer, params.k)) as usize;
+            }
+            if params.gc_histogram {
+                // a canonical k-mer has the GC content of both of its strands
+                shard.gc_histogram[gc_count(kmer) as usize] += 1;
+            }
+            if let Some(n) = params.minhash {
+                let hash = hash_kmer(kmer, params.seed);
+                if minhash.len() < n {
+                    minhash.push(hash);
+                } else if minhash.peek().is_some_and(|&max| hash < max) {
+                    minhash.pop();
+                    minhash.push(hash);
+                }
+            }
+            if I::STRANDS {
+                shard.strands[info.strands() as usize - 1] += 1;
+            }
+            let count = info.count();
+            if I::COUNTS {
+                shard.singletons += (count == 1) as usize;
+                shard.below_min += params.below_min(count) as usize;
+                shard.above_max += params.above_max(count) as usize;
+            }
+            if let Some(histo_max) = params.histo_max {
+                let bin = (count as usize).min(histo_max);
+                if shard.histogram.len() <= bin {
+                    shard.histogram.resize(bin + 1, 0);
+                }
+                shard.histogram[bin] += 1;
+                if bin == histo_max {
+                    shard.histogram_tail += count as usize;
+                }
+            }
+            if params.keep_kmers && params.is_solid(count) {
+                shard.kmers.push(kmer);
+                if I::COUNTS {
+                    shard.counts.push(count);
+                }
+            }
+        }
+        shard.solid = shard.distinct - shard.below_min - shard.above_max;
+        shard.minhash = minhash.into_sorted_vec();
+        if params.sort_kmers && params.method == CountMethod::Hash {
+            if I::COUNTS {
+                let (kmers, counts) = (take(&mut shard.kmers), take(&mut shard.counts));
+                let mut entries: Vec<_> = kmers.into_iter().zip(counts).collect();
+                entries.sort_unstable_by_key(|&(kmer, _)| kmer);
+                (shard.kmers, shard.counts) = entries.into_iter().unzip();
+            } else {
+                shard.kmers.sort_unstable();
+            }
+        }
+        shard
+    }
+}
+
+/// Groups the runs of equal k-mers of a sorted sequence, observing each occurrence.
+fn runs<I: KmerInfo>(kmers: impl Iterator<Item = (KT, u8)>) -> impl Iterator<Item = (KT, I)> {
+    let mut kmers = kmers.peekable();
+    std::iter::from_fn(move || {
+        let (kmer, strand) = kmers.next()?;
+        let mut info = I::default();
+        info.observe(strand);
+        while let Some((_, strand)) = kmers.next_if(|&(next, _)| next == kmer) {
+            info.observe(strand);
+        }
+        Some((kmer, info))
+    })
+}
+
+/// Counts the k-mers of every bucket in parallel, tracking what `params` asks for.
+/// Spilled super-k-mers are read back, one shard per thread at a time.
+pub fn count_shards(buckets: Vec<Bucket>, params: &CountParams) -> Result<Vec<ShardCount>> {
+    in_count_pool(params, || {
+        buckets
+            .into_par_iter()
+            .enumerate()
+            .map(|(shard, v)| {
+                let start = Instant::now();
+                let mut v = v.into_inner().unwrap();
+                if let Some(dir) = &params.spill_dir {
+                    unspill(dir, shard, &mut v).context("Failed to read spilled super-k-mers")?;
+                }
+                let count = match (params.counts, params.strands) {
+                    (false, false) => ShardCount::new::<()>(v, params),
+                    (false, true) => ShardCount::new::<u8>(v, params),
+                    (true, false) => ShardCount::new::<u32>(v, params),
+                    (true, true) => ShardCount::new::<(u32, u8)>(v, params),
+                };
+                let elapsed = 1000. * start.elapsed().as_secs_f64();
+                let superkmers = count.superkmers;
+                log!(
+                    3,
+                    "Counted shard {shard} ({superkmers} super-k-mers) in {elapsed:.03} ms"
+                );
+                Ok(count)
+            })
+            .collect()
+    })
+}
+
+/// Runs the counting `f` in a pool of `params.concurrent_shards` threads, in which each
+/// thread counts one shard at a time, or in the current pool if it is not set.
+fn in_count_pool<T: Send>(params: &CountParams, f: impl FnOnce() -> Result<T> + Send) -> Result<T> {
+    match params.concurrent_shards {
+        Some(n) => ThreadPoolBuilder::new().num_threads(n).build()?.install(f),
+        None => f(),
+    }
+}
+
+/// Sizes of two sets of distinct k-mers and of their intersection.
+#[derive(Clone, Debug, Default)]
+pub struct Comparison {
+    pub distinct: [usize; 2],
+    pub shared: usize,
+    /// the k-mers of the first set missing from the second, when they are kept
+    pub only_first: Vec<KT>,
+}
+
+impl Comparison {
+    /// Size of the intersection over the size of the union.
+    pub fn jaccard(&self) -> f64 {
+        let union = self.distinct[0] + self.distinct[1] - self.shared;
+        self.shared as f64 / union.max(1) as f64
+    }
+
+    /// Fraction of the k-mers of set `i` that are in the other one.
+    pub fn containment(&self, i: usize) -> f64 {
+        self.shared as f64 / self.distinct[i].max(1) as f64
+    }
+
+    /// Number of k-mers of set `i` that are not in the other one.
+    pub fn only(&self, i: usize) -> usize {
+        self.distinct[i] - self.shared
+    }
+}
+
+/// Compares the distinct k-mers of two sets of buckets collected with the same parameters,
+/// shard by shard: the k-mers of a shard only come from the super-k-mers of the same shard
+/// on both sides, so only two shards are expanded at once by each thread.
+/// The k-mers only in `a` are kept (sorted if asked) with `params.keep_kmers`.
+pub fn compare_shards(a: Vec<Bucket>, b: Vec<Bucket>, params: &CountParams) -> Result<Comparison> {
+    ensure!(a.len() == b.len(), "The buckets of both inputs must match");
+    in_count_pool(params, || {
+        let comparison = a
+            .into_par_iter()
+            .zip(b)
+            .map(|(a, b)| {
+                let (a, _) = count_shard::<()>(a.into_inner().unwrap(), params);
+                let (b, _) = count_shard::<()>(b.into_inner().unwrap(), params);
+                let mut only_first = Vec::new();
+                if params.keep_kmers {
+                    only_first.extend(a.keys().filter(|kmer| !b.contains_key(kmer)));
+                    if params.sort_kmers {
+                        only_first.sort_unstable();
+                    }
+                }
+                Comparison {
+                    distinct: [a.len(), b.len()],
+                    shared: a.keys().filter(|kmer| b.contains_key(kmer)).count(),
+                    only_first,
+                }
+            })
+            .reduce(Comparison::default, |mut x, mut y| {
+                x.distinct[0] += y.distinct[0];
+                x.distinct[1] += y.distinct[1];
+                x.shared += y.shared;
+                x.only_first.append(&mut y.only_first);
+                x
+            });
+        Ok(comparison)
+    })
+}
+
+/// Estimates the number of distinct k-mers of the buckets with a HyperLogLog sketch of
+/// `2^precision` registers per thread instead of counting them exactly.
+pub fn estimate_distinct(
+    buckets: Vec<Bucket>,
+    params: &CountParams,
+    precision: u32,
+) -> Result<Hll> {
+    in_count_pool(params, || {
+        buckets
+            .into_par_iter()
+            .enumerate()
+            .try_fold(
+                || Hll::new(precision),
+                |mut sketch, (shard, v)| {
+                    let mut v = v.into_inner().unwrap();
+                    if let Some(dir) = &params.spill_dir {
+                        unspill(dir, shard, &mut v)
+                            .context("Failed to read spilled super-k-mers")?;
+                    }
+                    for_each_sampled_kmer(v, params, |kmer, _| {
+                        sketch.insert(hash_kmer(kmer, params.seed))
+                    });
+                    Ok(sketch)
+                },
+            )
+            .try_reduce(
+                || Hll::new(precision),
+                |mut sketch, other| {
+                    sketch.merge(&other);
+                    Ok(sketch)
+                },
+            )
+    })
+}
+
+/// Approximates the number of distinct k-mers of the buckets with a Bloom filter of `bits`
+/// bits per shard: a k-mer is counted when the filter had not seen it yet, so the count
+/// is slightly low, by the k-mers that were false positives.
+pub fn count_with_bloom(buckets: Vec<Bucket>, params: &CountParams, bits: usize) -> Result<usize> {
+    in_count_pool(params, || {
+        buckets
+            .into_par_iter()
+            .enumerate()
+            .map(|(shard, v)| {
+                let mut v = v.into_inner().unwrap();
+                if let Some(dir) = &params.spill_dir {
+                    unspill(dir, shard, &mut v).context("Failed to read spilled super-k-mers")?;
+                }
+                let mut filter = Bloom::new(bits);
+                let mut distinct = 0;
+                for_each_sampled_kmer(v, params, |kmer, _| {
+                    distinct += filter.insert(hash_kmer(kmer, params.seed)) as usize
+                });
+                Ok(distinct)
+            })
+            .sum()
+    })
+}
+
+/// File into which the super-k-mers of `shard` are spilled.
+fn spill_path(dir: &Path, shard: usize) -> PathBuf {
+    dir.join(format!("distinct-kmers-{}-{shard}.skm", std::process::id()))
+}
+
+/// Appends the super-k-mers of a bucket to its spill file, as raw little-endian words,
+/// and empties it (keeping its capacity).
+fn spill(dir: &Path, shard: usize, bucket: &mut Vec<SKT>) -> io::Result<()> {
+    let path = spill_path(dir, shard);
+    let mut out = BufWriter::new(OpenOptions::new().create(true).append(true).open(path)?);
+    for skmer in bucket.drain(..) {
+        out.write_all(&skmer.to_le_bytes())?;
+    }
+    out.flush()
+}
+
+/// Adds the super-k-mers spilled for `shard`, if any, to its bucket and removes the file.
+fn unspill(dir: &Path, shard: usize, bucket: &mut Vec<SKT>) -> io::Result<()> {
+    let path = spill_path(dir, shard);
+    let bytes = match std::fs::read(&path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        bytes => bytes?,
+    };
+    let skmers = bytes.chunks_exact(size_of::<SKT>());
+    bucket.extend(skmers.map(|b| SKT::from_le_bytes(b.try_into().unwrap())));
+    std::fs::remove_file(path)
+}
+
+/// Expands the super-k-mers of a bucket into a map of its distinct k-mers, also returning the
+/// numbers of k-mers removed by the composition filters.
+fn count_shard<I: KmerInfo>(
+    v: Vec<SKT>,
+    params: &CountParams,
+) -> (HashMap<KT, I, FxBuildHasher>, Filtered) {
+    // there are at most as many distinct k-mers as k-mers, and usually far fewer with some
+    // coverage: past the ceiling, growing the map is cheaper than over-reserving it
+    let kmers = kmers_in(&v, params.k) / params.scaled.unwrap_or(1) as usize;
+    let capacity = kmers.min(params.map_capacity);
+    let mut map: HashMap<KT, I, FxBuildHasher> =
+        HashMap::with_capacity_and_hasher(capacity, FxBuildHasher);
+    let filtered = for_each_sampled_kmer(v, params, |kmer, strand| {
+        map.entry(kmer).or_default().observe(strand)
+    });
+    (map, filtered)
+}
+
+/// Number of distinct super-k-mers of a bucket, which is sorted. Their length is in their low
+/// bits, so super-k-mers of different lengths are never equal.
+fn count_distinct_superkmers(v: &mut [SKT]) -> usize {
+    v.sort_unstable();
+    v.chunk_by(|a, b| a == b).count()
+}
+
+/// Number of k-mers (with multiplicity) of the super-k-mers of a bucket.
+fn kmers_in(v: &[SKT], k: usize) -> usize {
+    v.iter()
+        .map(|&skmer| (low(skmer) as usize & SKLEN_MASK) - k + 1)
+        .sum()
+}
+
+/// Calls `f` on each k-mer of a bucket (and the strands on which it was read) that is kept
+/// by the subsampling, if any. Returns the numbers of k-mers removed by the composition
+/// filters.
+fn for_each_sampled_kmer(v: Vec<SKT>, params: &CountParams, mut f: impl FnMut(KT, u8)) -> Filtered {
+    let max_hash = u64::MAX / params.scaled.unwrap_or(1);
+    let mut filtered = Filtered::default();
+    for skmer in v {
+        filtered += for_each_counted_kmer(skmer, params, |kmer, strand| {
+            if params.scaled.is_none() || hash_kmer(kmer, params.seed) <= max_hash {
+                f(kmer, strand)
+            }
+        });
+    }
+    filtered
+}
+
+/// Calls `f` on each k-mer of a super-k-mer as counted with `params`: contiguous, or the
+/// spaced seeds gathered from its windows of `k` bases. The k-mers outside of the GC content
+/// range or of too low an entropy are skipped, and their numbers are returned.
+#[inline(always)]
+fn for_each_counted_kmer(skmer: SKT, params: &CountParams, mut f: impl FnMut(KT, u8)) -> Filtered {
+    let mut filtered = Filtered::default();
+    let bases = params.spaced.as_ref().map_or(params.k, Vec::len);
+    let mut f = |kmer: KT, strand: u8| match (&params.gc, params.min_entropy) {
+        (Some(gc), _) if !gc.contains(&gc_count(kmer)) => filtered.gc += 1,
+        (_, Some(min)) if entropy(kmer, bases) < min => filtered.low_entropy += 1,
+        _ => f(kmer, strand),
+    };
+    if let Some(care) = &params.spaced {
+        for_each_kmer(skmer, params.k, false, |window, _| {
+            let kmer = gather(window, care);
+            if params.canonical {
+                // the same pattern read on the other strand
+                let rc = gather(revcomp(window, params.k), care);
+                let strand = ((kmer <= rc) as u8 * FORWARD) | ((kmer >= rc) as u8 * REVERSE);
+                f(kmer.min(rc), strand);
+            } else {
+                f(kmer, FORWARD);
+            }
+        });
+    } else {
+        for_each_kmer(skmer, params.k, params.canonical, &mut f);
+    }
+    filtered
+}
+
+/// K-mers (with multiplicity) removed by the composition filters.
+#[derive(Clone, Copy, Default)]
+struct Filtered {
+    gc: usize,
+    low_entropy: usize,
+}
+
+impl AddAssign for Filtered {
+    fn add_assign(&mut self, other: Self) {
+        self.gc += other.gc;
+        self.low_entropy += other.low_entropy;
+    }
+}
+
+/// Shannon entropy, in bits, of the base composition of a k-mer of `k` bases: from 0 for a
+/// homopolymer to 2 when the four bases are equally frequent.
+#[inline(always)]
+fn entropy(kmer: KT, k: usize) -> f64 {
+    // the low and high bits of the bases: A=00, C=01, T=10, G=11
+    let (low, high) = (kmer & (KT::MAX / 3), (kmer >> 1) & (KT::MAX / 3));
+    let c = (low & !high).count_ones() as usize;
+    let t = (!low & high).count_ones() as usize;
+    let g = (low & high).count_ones() as usize;
+    [k - c - t - g, c, t, g]
+        .into_iter()
+        .filter(|&n| n > 0)
+        .map(|n| {
+            let p = n as f64 / k as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Number of G and C bases of a k-mer: with A=0, C=1, T=2 and G=3, they are the bases with
+/// their low bit set.
+#[inline(always)]
+fn gc_count(kmer: KT) -> u32 {
+    (kmer & (KT::MAX / 3)).count_ones()
+}
+
+/// Packs the bases of `window` at the `care` positions, first one in the low bits.
+fn gather(window: KT, care: &[usize]) -> KT {
+    care.iter().enumerate().fold(0, |kmer, (i, &pos)| {
+        kmer | (((window >> (2 * pos)) & 3) << (2 * i))
+    })
+}
+
+/// Hash of a k-mer for subsampling and sketching: the 64-bit finalizer of MurmurHash3
+/// (`fmix64`), a bijection on `u64`, of the 2-bit k-mer (first base in the low bits) XORed
+/// with `seed`.
+/// It is applied to the canonical k-mer (not the minimizer), so that the subsample does not
+/// depend on the strand nor on how the k-mers were partitioned into super-k-mers.
+#[allow(clippy::unnecessary_cast)] // KT is not necessarily u64
+pub fn hash_kmer(kmer: KT, seed: u64) -> u64 {
+    // fold the high half of wide k-mers, a no-op for u64 ones
+    fmix64((kmer ^ (kmer >> 32 >> 32)) as u64 ^ seed)
+}
+
+/// Hash of a record id for subsampling: FNV-1a from `seed`, then `fmix64`. It does not depend
+/// on the platform nor on the version, so the same records are kept on every run.
+fn hash_id(id: &[u8], seed: u64) -> u64 {
+    let h = id.iter().fold(seed ^ 0xcbf29ce484222325, |h, &c| {
+        (h ^ c as u64).wrapping_mul(0x100000001b3)
+    });
+    fmix64(h)
+}
+
+/// The 64-bit finalizer of MurmurHash3.
+fn fmix64(mut h: u64) -> u64 {
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51afd7ed558ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ceb9fe1a85ec53);
+    h ^ (h >> 33)
+}
+
+/// Calls `f` on each k-mer of a super-k-mer (canonical if `canonical`) along with the
+/// strand(s) on which it was read.
+#[inline(always)]
+fn for_each_kmer(skmer: SKT, k: usize, canonical: bool, mut f: impl FnMut(KT, u8)) {
+    let kmer_mask = KT::MAX >> (KT::BITS as usize - 2 * k);
+    let len = low(skmer) as usize & SKLEN_MASK;
+    let skmer = skmer >> SKLEN_BITS;
+    // the k-mers and their reverse complements are rolled base by base rather than shifted out
+    // of the super-k-mer and reversed one at a time
+    let mut kmer = low(skmer) & kmer_mask; // start with low bits
+    let mut rc = if canonical { revcomp(kmer, k) } else { 0 };
+    let mut next = skmer >> (2 * k);
+    for _ in 0..(len - k + 1) {
+        if canonical {
+            // a palindrome is its own reverse complement, so it is seen on both strands
+            let strand = ((kmer <= rc) as u8 * FORWARD) | ((kmer >= rc) as u8 * REVERSE);
+            f(kmer.min(rc), strand);
+        } else {
+            f(kmer, FORWARD);
+        }
+        // the next base enters the k-mer in the high bits and its complement (`base ^ 2`)
+        // the reverse complement in the low bits; past the end, the bases are unused zeros
+        let base = low(next) & 3;
+        next >>= 2;
+        kmer = (kmer >> 2) | (base << (2 * (k - 1)));
+        rc = ((rc << 2) | (base ^ 2)) & kmer_mask;
+    }
+}
+
+/// Appends the ASCII sequence of a k-mer to `out`.
+pub fn decode(kmer: KT, k: usize, out: &mut Vec<u8>) {
+    out.extend((0..k).map(|i| b"ACTG"[(kmer >> (2 * i)) as usize & 3]));
+}
+
+/// Appends the bases of a super-k-mer to `out`: its length is in its low bits, followed by
+/// its 2-bit bases, first base in the low bits.
+pub fn decode_superkmer(skmer: SKT, out: &mut Vec<u8>) {
+    let len = low(skmer) as usize & SKLEN_MASK;
+    let bases = skmer >> SKLEN_BITS;
+    out.extend((0..len).map(|i| b"ACTG"[low(bases >> (2 * i)) as usize & 3]));
+}
+
+// The output is wrapped in a Result to allow matching on errors.
+// Returns an Iterator to the Reader of the lines of the file.
+fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
+where P: AsRef<Path>, {
+    let file = File::open(filename)?;
+    Ok(io::BufReader::new(file).lines())
+}
+
+/// Reads the paths listed in a file of files, one per line.
+pub fn read_file_list(path: &str) -> Result<Vec<String>> {
+    read_lines(path)
+        .and_then(|lines| lines.collect())
+        .with_context(|| format!("Failed to read {path}"))
+}
+
+/// Expands a glob pattern into the matching paths, in alphabetical order.
+pub fn expand_glob(pattern: &str) -> Result<Vec<String>> {
+    let mut matches = Vec::new();
+    for path in glob::glob(pattern).with_context(|| format!("Invalid glob pattern {pattern}"))? {
+        matches.push(path?.to_string_lossy().into_owned());
+    }
+    ensure!(!matches.is_empty(), "No file matches {pattern}");
+    Ok(matches)
+}
+
+/// Whether `path` looks like a (possibly compressed) FASTA or FASTQ file.
+fn is_sequence_file(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let name = name
+        .strip_suffix(".gz")
+        .or_else(|| name.strip_suffix(".zst"))
+        .or_else(|| name.strip_suffix(".bz2"))
+        .or_else(|| name.strip_suffix(".xz"))
+        .unwrap_or(&*name);
+    [".fa", ".fasta", ".fq", ".fastq"]
+        .iter()
+        .any(|ext| name.ends_with(ext))
+}
+
+/// Appends the sequence files of `dir` to `files`, descending into subdirectories if `recursive`.
+fn list_sequence_files(dir: &Path, recursive: bool, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            if recursive {
+                list_sequence_files(&path, recursive, files)?;
+            }
+        } else if is_sequence_file(&path) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Opens an input file for reading, `-` meaning stdin, decompressing it if needed.
+/// With `progress`, the bytes read (before decompression) are shown on stderr.
+fn open_input(path: &Path, progress: bool) -> Result<Box<dyn Read + Send>> {
+    let reader: Box<dyn Read + Send> = if path == Path::new("-") {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(path)?)
+    };
+    let mut reader: Box<dyn Read + Send> = if progress {
+        Box::new(progress_bar(path).wrap_read(reader))
+    } else {
+        reader
+    };
+    // niffler needs 5 bytes to sniff the compression: shorter inputs (empty ones included)
+    // can only be plain text
+    let mut head = Vec::with_capacity(5);
+    reader.by_ref().take(5).read_to_end(&mut head)?;
+    let short = head.len() < 5;
+    let compression = compression_name(&head);
+    let reader: Box<dyn Read + Send> = Box::new(io::Cursor::new(head).chain(reader));
+    if short {
+        return Ok(reader);
+    }
+    match get_reader(reader) {
+        Ok((reader, _)) => Ok(reader),
+        Err(niffler::Error::FeatureDisabled) => bail!(
+            "{} is {compression} compressed, which this build cannot decompress",
+            path.display()
+        ),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Name of the compression format of a file starting with `head`, for the formats niffler
+/// knows.
+fn compression_name(head: &[u8]) -> &'static str {
+    match head {
+        [0x1f, 0x8b, ..] => "gzip",
+        [b'B', b'Z', b'h', ..] => "bzip2",
+        [0xfd, b'7', b'z', b'X', b'Z', ..] => "xz",
+        [0x5d, 0x00, 0x00, ..] => "lzma",
+        [0x28, 0xb5, 0x2f, 0xfd, ..] => "zstd",
+        _ => "unknown",
+    }
+}
+
+/// A progress bar for reading `path`: a percentage and an ETA when its size is known,
+/// only the throughput otherwise (e.g. for stdin).
+fn progress_bar(path: &Path) -> ProgressBar {
+    let len = std::fs::metadata(path)
+        .ok()
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len());
+    let bar = if let Some(len) = len {
+        let template = "{msg} [{bar:40}] {percent}% at {binary_bytes_per_sec}, ETA {eta}";
+        ProgressBar::new(len).with_style(ProgressStyle::with_template(template).unwrap())
+    } else {
+        let template = "{msg} {spinner} {binary_bytes} at {binary_bytes_per_sec}";
+        let bar = ProgressBar::new_spinner();
+        bar.enable_steady_tick(Duration::from_millis(100));
+        bar.with_style(ProgressStyle::with_template(template).unwrap())
+    };
+    bar.with_message(path.display().to_string())
+        .with_finish(ProgressFinish::AndLeave)
+}
+
+/// Whether the records of the input at `path` are FASTQ rather than FASTA, from its first
+/// byte that is not whitespace (`@` or `>`), which is left to be read. `fastq` tells whether
+/// FASTQ was asked for (the format of an empty input), and its contradiction is an error.
+fn detect_fastq(reader: &mut impl BufRead, path: &Path, fastq: bool) -> Result<bool> {
+    let first = loop {
+        let buf = reader
+            .fill_buf()
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let Some(&first) = buf.first() else {
+            return Ok(fastq);
+        };
+        if !first.is_ascii_whitespace() {
+            break first;
+        }
+        reader.consume(1);
+    };
+    match first {
+        b'@' => Ok(true),
+        b'>' => {
+            ensure!(
+                !fastq,
+                "{} is FASTA, not FASTQ (drop --fastq)",
+                path.display()
+            );
+            Ok(false)
+        }
+        c => bail!(
+            "{} is neither FASTA nor FASTQ, it starts with {:?}",
+            path.display(),
+            c as char
+        ),
+    }
+}
+
+/// Collects the super-k-mers of a single file into the buckets.
+fn process_file<P: AsRef<Path>>(
+    path: P,
+    mut processor: SuperkmerCollector,
+    params: &CollectParams,
+) -> Result<()> {
+    let path = path.as_ref();
+    let start = Instant::now();
+    let context = || format!("Failed to read {}", path.display());
+    let mut reader = BufReader::new(open_input(path, params.progress).with_context(context)?);
+    processor.is_fastq = detect_fastq(&mut reader, path, params.is_fastq)?;
+    if processor.is_fastq {
+        let reader = fastq::Reader::new(reader);
+        reader
+            .process_parallel(processor, params.threads)
+            .with_context(context)?;
+    } else {
+        let reader = fasta::Reader::new(reader);
+        reader
+            .process_parallel(processor, params.threads)
+            .with_context(context)?;
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    log!(2, "Read {} in {elapsed:.02} s", path.display());
+    Ok(())
+}
+
+/// Collects the super-k-mers of the inputs into buckets.
+/// With `failed`, the files of `@FILE` lists that cannot be read are skipped and recorded
+/// there instead of failing the whole collection.
+pub fn collect_superkmers(
+    params: &CollectParams,
+    paths: &[String],
+    mates: Option<&str>,
+    minimizers: Option<&Mutex<MinimizerCounts>>,
+    per_record: Option<&RecordWriter>,
+    failed: Option<&Mutex<Vec<String>>>,
+) -> Result<Vec<Bucket>> {
+    let (match_n, match_newline) = break_regexes(params);
+    // reserved when they are first filled
+    let buckets: Vec<Bucket> = (0..params.shards()).map(|_| Bucket::default()).collect();
+    let stats = CollectStats::default();
+    let processor = SuperkmerCollector::new(
+        params,
+        &buckets,
+        &match_n,
+        &match_newline,
+        minimizers,
+        per_record,
+        &stats,
+    );
+    read_inputs(params, paths, mates, processor, &stats, failed)?;
+    if params.interleaved {
+        report_pairs(&stats);
+    }
+    if stats.reached(params.max_records) {
+        let records = stats.records.load(Ordering::Relaxed);
+        log!(1, "Stopped after {records} records (--max-records)");
+    }
+    if let Some(fraction) = params.subsample {
+        let records = stats.records.load(Ordering::Relaxed);
+        let kept = records - stats.subsampled_out.load(Ordering::Relaxed);
+        log!(
+            1,
+            "Kept {kept} of {records} records (subsampling a fraction of {fraction})"
+        );
+    }
+    if params.include_contigs.is_some() || params.exclude_contigs.is_some() {
+        let filtered_out = stats.filtered_out.load(Ordering::Relaxed);
+        log!(1, "Skipped {filtered_out} records by their name");
+    }
+    let min_length = params.min_length;
+    if min_length > 0 {
+        let short_records = stats.short_records.into_inner();
+        log!(
+            1,
+            "Skipped {short_records} records shorter than {min_length}"
+        );
+    }
+    if params.min_mean_qual.is_some() {
+        let low_quality = stats.low_quality.into_inner();
+        log!(1, "Skipped {low_quality} reads of too low mean quality");
+    }
+    Ok(buckets)
+}
+
+/// First pass of a two-pass collection: reads the inputs as `collect_superkmers` does, but
+/// only counts the super-k-mers of each bucket, so that the second pass can reserve exactly
+/// as much (see `CollectParams::bucket_sizes`). The inputs must be read twice, so they
+/// cannot be stdin. Like `failed` for `collect_superkmers`, `skip_failed` skips the listed
+/// files that cannot be read, which the second pass then records.
+pub fn size_buckets(
+    params: &CollectParams,
+    paths: &[String],
+    mates: Option<&str>,
+    skip_failed: bool,
+) -> Result<Vec<usize>> {
+    let (match_n, match_newline) = break_regexes(params);
+    // never filled, only their number matters
+    let buckets: Vec<Bucket> = (0..params.shards()).map(|_| Bucket::default()).collect();
+    let sizes: Vec<AtomicUsize> = (0..params.shards()).map(|_| AtomicUsize::new(0)).collect();
+    let stats = CollectStats::default();
+    let mut processor = SuperkmerCollector::new(
+        params,
+        &buckets,
+        &match_n,
+        &match_newline,
+        None,
+        None,
+        &stats,
+    );
+    processor.sizes = Some(&sizes);
+    let failed = Mutex::default();
+    let failed = skip_failed.then_some(&failed);
+    read_inputs(params, paths, mates, processor, &stats, failed)?;
+    Ok(sizes.into_iter().map(AtomicUsize::into_inner).collect())
+}
+
+/// Reads the inputs as `collect_superkmers` does, but hands each super-k-mer to `sink` along
+/// with its shard, in `0..params.shards()`, instead of keeping it in a bucket: e.g. to write
+/// the partition to disk or to feed another aggregator. `sink` is called from all the threads
+/// at once, and its errors stop the collection. The super-k-mers are encoded as in the
+/// buckets, see `decode_superkmer`.
+pub fn for_each_superkmer(
+    params: &CollectParams,
+    paths: &[String],
+    mates: Option<&str>,
+    sink: &SuperkmerSink<'_>,
+    failed: Option<&Mutex<Vec<String>>>,
+) -> Result<()> {
+    let (match_n, match_newline) = break_regexes(params);
+    // never filled, only their number matters
+    let buckets: Vec<Bucket> = (0..params.shards()).map(|_| Bucket::default()).collect();
+    let stats = CollectStats::default();
+    let mut processor = SuperkmerCollector::new(
+        params,
+        &buckets,
+        &match_n,
+        &match_newline,
+        None,
+        None,
+        &stats,
+    );
+    processor.sink = Some(sink);
+    read_inputs(params, paths, mates, processor, &stats, failed)
+}
+
+/// Reads the inputs as `collect_superkmers` does, down to the parsing of every record, but
+/// only returns their numbers of records and of bases, to validate them cheaply before a
+/// long run. `failed` is as for `collect_superkmers`.
+pub fn check_inputs(
+    params: &CollectParams,
+    paths: &[String],
+    mates: Option<&str>,
+    failed: Option<&Mutex<Vec<String>>>,
+) -> Result<(usize, usize)> {
+    let (match_n, match_newline) = break_regexes(params);
+    let stats = CollectStats::default();
+    let mut processor =
+        SuperkmerCollector::new(params, &[], &match_n, &match_newline, None, None, &stats);
+    processor.check_only = true;
+    read_inputs(params, paths, mates, processor, &stats, failed)?;
+    if params.interleaved {
+        report_pairs(&stats);
+    }
+    Ok((stats.records.into_inner(), stats.bases.into_inner()))
+}
+
+/// The regexes of the bases that break the sequences and of the newlines.
+fn break_regexes(params: &CollectParams) -> (Regex, Regex) {
+    // N and the other IUPAC ambiguity codes break the sequence, and so do soft-masked bases
+    // when they are skipped
+    let breaks = if params.skip_masked {
+        r"[NRYSWKMBDHVnryswkmbdhvacgtu]+"
+    } else {
+        r"[NRYSWKMBDHVnryswkmbdhv]+"
+    };
+    let match_n = RegexBuilder::new(breaks).unicode(false).build().unwrap();
+    let match_newline = RegexBuilder::new(r"[\r\n]+")
+        .unicode(false)
+        .build()
+        .unwrap();
+    (match_n, match_newline)
+}
+
+/// Warns about an input already `seen`, however its path is written, or fails with `params.strict`.
+fn check_duplicate(path: &Path, seen: &mut HashSet<PathBuf>, params: &CollectParams) -> Result<()> {
+    if path == Path::new("-") {
+        return Ok(());
+    }
+    // a missing file fails later, when it is read
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if !seen.insert(canonical) {
+        let path = path.display();
+        ensure!(!params.strict, "{path} is given twice as an input");
+        eprintln!("Warning: {path} is given twice as an input, its k-mers are counted twice");
+    }
+    Ok(())
+}
+
+/// Feeds the records of the inputs (and of their mates, if any) to `processor`, which is
+/// dropped once they have all been read.
+fn read_inputs(
+    params: &CollectParams,
+    paths: &[String],
+    mates: Option<&str>,
+    processor: SuperkmerCollector,
+    stats: &CollectStats,
+    failed: Option<&Mutex<Vec<String>>>,
+) -> Result<()> {
+    let mut seen = HashSet::new();
+    if let Some(mates) = mates {
+        // paired-end reads: the mates of the single input, counted into the same buckets
+        check_duplicate(Path::new(&paths[0]), &mut seen, params)?;
+        check_duplicate(Path::new(mates), &mut seen, params)?;
+        process_file(&paths[0], processor.clone(), params)?;
+        let first = stats.records.load(Ordering::Relaxed);
+        process_file(mates, processor.clone(), params)?;
+        let second = stats.records.load(Ordering::Relaxed) - first;
+        if first != second {
+            eprintln!(
+                "Warning: {} has {first} reads but {mates} has {second}, is one of them truncated?",
+                paths[0]
+            );
+        }
+    } else {
+        for path in paths {
+            if stats.reached(params.max_records) {
+                break;
+            }
+            // if path starts with @ this is a file of file names
+            if path.starts_with('@') {
+                for local_path in read_file_list(path)? {
+                    if stats.reached(params.max_records) {
+                        break;
+                    }
+                    check_duplicate(Path::new(&local_path), &mut seen, params)?;
+                    log!(1, "Counting for {local_path}");
+                    let result = process_file(&local_path, processor.clone(), params);
+                    match (result, failed) {
+                        (Err(e), Some(failed)) => {
+                            // what was read of it before the error is still counted
+                            eprintln!("Error: {e:#}, skipping {local_path}");
+                            failed.lock().unwrap().push(local_path);
+                        }
+                        (result, _) => result?,
+                    }
+                }
+            } else if Path::new(path).is_dir() {
+                let mut files = Vec::new();
+                list_sequence_files(Path::new(path), params.recursive, &mut files)?;
+                files.sort();
+                log!(1, "Found {} sequence files in {}", files.len(), path);
+                for local_path in files {
+                    if stats.reached(params.max_records) {
+                        break;
+                    }
+                    check_duplicate(&local_path, &mut seen, params)?;
+                    log!(1, "Counting for {}", local_path.display());
+                    process_file(local_path, processor.clone(), params)?;
+                }
+            } else {
+                check_duplicate(Path::new(path), &mut seen, params)?;
+                if paths.len() > 1 {
+                    log!(1, "Counting for {path}");
+                }
+                process_file(path, processor.clone(), params)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A set of distinct k-mers, sorted for binary search.
+pub struct KmerSet {
+    kmers: Vec<KT>,
+    k: usize,
+    canonical: bool,
+}
+
+impl KmerSet {
+    /// A set of `k`-mers, all canonical if `canonical`.
+    pub fn new(mut kmers: Vec<KT>, k: usize, canonical: bool) -> Self {
+        kmers.sort_unstable();
+        Self {
+            kmers,
+            k,
+            canonical,
+        }
+    }
+
+    /// Loads a set written by `--binary-out` (raw little-endian k-mers, sorted shard by shard,
+    /// which do not record `k` nor whether they are canonical) or by `--save-index`.
+    pub fn load(path: &Path, k: usize, canonical: bool) -> Result<Self> {
+        ensure!((1..=MAX_K).contains(&k), "k must be between 1 and {MAX_K}");
+        if index::is_index(path).with_context(|| format!("Failed to read {}", path.display()))? {
+            let mut index = IndexReader::open(path)?;
+            index.check(k, canonical)?;
+            let mut kmers = Vec::with_capacity(index.len() as usize);
+            for shard in 0..index.shards() {
+                kmers.extend(index.shard(shard).context("Failed to read the index")?);
+            }
+            kmers.sort_unstable();
+            return Ok(Self {
+                kmers,
+                k,
+                canonical,
+            });
+        }
+        let bytes =
+            std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        ensure!(
+            bytes.len() % size_of::<KT>() == 0,
+            "{} is not a binary k-mer set",
+            path.display()
+        );
+        let mut kmers: Vec<KT> = bytes
+            .chunks_exact(size_of::<KT>())
+            .map(|b| KT::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        kmers.sort_unstable();
+        Ok(Self {
+            kmers,
+            k,
+            canonical,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.kmers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kmers.is_empty()
+    }
+
+    pub fn contains(&self, kmer: KT) -> bool {
+        self.kmers.binary_search(&kmer).is_ok()
+    }
+
+    /// Numbers of k-mers of `seq` in the set and in total (canonical if the set is).
+    /// Bases other than ACGTU break the k-mers, newlines are skipped.
+    pub fn query(&self, seq: &[u8]) -> (usize, usize) {
+        let (mut present, mut total) = (0, 0);
+        self.scan(seq, |_, found| {
+            total += 1;
+            present += found as usize;
+        });
+        (present, total)
+    }
+
+    /// Calls `f(start, found)` for each k-mer of `seq`, in order, with the position of its first
+    /// base (newlines are not counted as bases) and whether it is in the set.
+    fn scan(&self, seq: &[u8], mut f: impl FnMut(usize, bool)) {
+        let k = self.k;
+        let kmer_mask = KT::MAX >> (KT::BITS as usize - 2 * k);
+        let (mut kmer, mut rc, mut len): (KT, KT, usize) = (0, 0, 0);
+        let mut pos = 0;
+        for &c in seq {
+            if c == b'\n' || c == b'\r' {
+                continue;
+            }
+            pos += 1;
+            if !matches!(c.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T' | b'U') {
+                len = 0;
+                continue;
+            }
+            // same 2-bit code as `packed_seq`, first base in the low bits; complement is ^2
+            let code = ((c >> 1) & 3) as KT;
+            kmer = (kmer >> 2) | (code << (2 * (k - 1)));
+            rc = ((rc << 2) | (code ^ 2)) & kmer_mask;
+            len += 1;
+            if len >= k {
+                let kmer = if self.canonical { kmer.min(rc) } else { kmer };
+                f(pos - k, self.contains(kmer));
+            }
+        }
+    }
+
+    /// Writes the `<record id>\t<start>\t<end>` BED intervals of the positions of a record
+    /// at which a k-mer of the set starts, merging the adjacent ones. Returns the number of
+    /// intervals and of positions.
+    fn write_intervals(
+        &self,
+        id: &[u8],
+        seq: &[u8],
+        out: &mut impl Write,
+    ) -> io::Result<(usize, usize)> {
+        let (mut intervals, mut positions) = (0, 0);
+        let mut run: Option<Range<usize>> = None;
+        let mut line = vec![];
+        let mut result = Ok(());
+        let mut flush = |run: Range<usize>| {
+            intervals += 1;
+            positions += run.len();
+            line.clear();
+            line.extend_from_slice(id);
+            writeln!(line, "\t{}\t{}", run.start, run.end).unwrap();
+            if result.is_ok() {
+                result = out.write_all(&line);
+            }
+        };
+        self.scan(seq, |start, found| match &mut run {
+            Some(r) if found && r.end == start => r.end += 1,
+            _ => {
+                if let Some(r) = run.take() {
+                    flush(r);
+                }
+                if found {
+                    run = Some(start..start + 1);
+                }
+            }
+        });
+        if let Some(r) = run {
+            flush(r);
+        }
+        result.map(|()| (intervals, positions))
+    }
+}
+
+/// Writes to `out`, for each record of the reference at `path` in turn, the BED intervals of
+/// the positions at which a k-mer of `set` starts. Returns the number of intervals and of
+/// positions.
+pub fn write_bed(
+    set: &KmerSet,
+    path: &str,
+    is_fastq: bool,
+    out: &mut impl Write,
+) -> Result<(usize, usize)> {
+    let path = Path::new(path);
+    let context = || format!("Failed to read {}", path.display());
+    let mut reader = BufReader::new(open_input(path, false).with_context(context)?);
+    let (mut intervals, mut positions) = (0, 0);
+    let mut write_record = |head: &[u8], seq: &[u8]| -> Result<()> {
+        let id = head.split(u8::is_ascii_whitespace).next().unwrap_or(head);
+        let (n, len) = set.write_intervals(id, seq, &mut *out)?;
+        (intervals, positions) = (intervals + n, positions + len);
+        Ok(())
+    };
+    if detect_fastq(&mut reader, path, is_fastq)? {
+        let mut reader = fastq::Reader::new(reader);
+        while let Some(record) = reader.next() {
+            let record = record.with_context(context)?;
+            write_record(record.head(), record.seq())?;
+        }
+    } else {
+        let mut reader = fasta::Reader::new(reader);
+        while let Some(record) = reader.next() {
+            let record = record.with_context(context)?;
+            write_record(record.head(), record.seq())?;
+        }
+    }
+    Ok((intervals, positions))
+}
+
+/// Writes, for each record it reads, its id and how many of its k-mers are in a set.
+#[derive(Clone)]
+struct KmerQuery<'a> {
+    set: &'a KmerSet,
+    out: &'a RecordWriter,
+}
+
+impl ParallelProcessor for KmerQuery<'_> {
+    fn process_record<'a, Rf: MinimalRefRecord<'a>>(&mut self, record: Rf) -> Result<()> {
+        let (present, total) = self.set.query(record.ref_seq());
+        let fraction = present as f64 / total.max(1) as f64;
+        let head = record.ref_head();
+        let id = head.split(u8::is_ascii_whitespace).next().unwrap_or(head);
+        let mut line = id.to_vec();
+        writeln!(line, "\t{present}\t{total}\t{fraction:.04}")?;
+        self.out.lock().unwrap().write_all(&line)?;
+        Ok(())
+    }
+}
+
+/// Writes `<record id>\t<k-mers in the set>\t<k-mers>\t<fraction>` lines to `out` for the
+/// records of `path`, in no particular order.
+pub fn query_file(
+    set: &KmerSet,
+    path: &str,
+    is_fastq: bool,
+    threads: usize,
+    out: &RecordWriter,
+) -> Result<()> {
+    let path = Path::new(path);
+    let context = || format!("Failed to read {}", path.display());
+    let mut reader = BufReader::new(open_input(path, false).with_context(context)?);
+    let processor = KmerQuery { set, out };
+    if detect_fastq(&mut reader, path, is_fastq)? {
+        let reader = fastq::Reader::new(reader);
+        reader
+            .process_parallel(processor, threads)
+            .with_context(context)
+    } else {
+        let reader = fasta::Reader::new(reader);
+        reader
+            .process_parallel(processor, threads)
+            .with_context(context)
+    }
+}
+
+/// Counter of the distinct (canonical) k-mers of sequence files, with the default settings
+/// of the command-line tool.
+pub struct DistinctKmerCounter {
+    pub k: usize,
+    /// minimizer size
+    pub m: usize,
+    pub threads: usize,
+    /// count canonical k-mers, which requires an odd k
+    pub canonical: bool,
+    /// the inputs are FASTQ files rather than FASTA ones
+    pub fastq: bool,
+    /// number of minimizer bases selecting the bucket of a super-k-mer
+    pub shard_bases: usize,
+    pub method: CountMethod,
+}
+
+/// Results of [`DistinctKmerCounter::count`].
+#[derive(Debug, Clone)]
+pub struct CountResult {
+    pub distinct: usize,
+    /// k-mers with multiplicity
+    pub total: usize,
+    pub superkmers: usize,
+    pub collect_seconds: f64,
+    pub count_seconds: f64,
+}
+
+/// Distinct k-mers kept shard by shard, as returned by [`DistinctKmerCounter::shard_sets`].
+pub struct ShardSets {
+    /// the distinct k-mers of each shard, which no other shard has
+    pub shards: Vec<HashSet<KT, FxBuildHasher>>,
+    /// number of minimizer bases selecting the shard of a k-mer
+    pub shard_bases: usize,
+}
+
+impl ShardSets {
+    /// Shard of the k-mers of the super-k-mers of a (canonical, if the k-mers are) minimizer:
+    /// the 2-bit encoding of its first `shard_bases` bases (all of them if it is shorter),
+    /// first base in the low bits, so that shard `i` holds the k-mers whose minimizers start
+    /// with the bases that [`decode`] gives for `i`.
+    pub fn shard_of(&self, minimizer: KT) -> usize {
+        minimizer as usize & (self.shards.len() - 1)
+    }
+
+    /// Total number of distinct k-mers.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(HashSet::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(HashSet::is_empty)
+    }
+}
+
+impl DistinctKmerCounter {
+    /// Canonical k-mers of FASTA files, with minimizers of size 21 (or k if smaller) on all
+    /// the threads of the current rayon pool.
+    pub fn new(k: usize) -> Self {
+        Self {
+            k,
+            m: k.min(21),
+            threads: current_num_threads(),
+            canonical: true,
+            fastq: false,
+            shard_bases: DEFAULT_SHARD_BASES,
+            method: CountMethod::Hash,
+        }
+    }
+
+    fn collect_params(&self) -> CollectParams {
+        CollectParams {
+            k: self.k,
+            m: self.m,
+            canonical: self.canonical,
+            rna: false,
+            is_fastq: self.fastq,
+            threads: self.threads,
+            recursive: false,
+            min_length: 0,
+            skip_masked: false,
+            upper_only: false,
+            normalize_case: false,
+            chunk_bases: None,
+            interleaved: false,
+            strict: false,
+            window: None,
+            trim_qual: None,
+            min_mean_qual: None,
+            progress: false,
+            bucket_cap: bucket_cap(DEFAULT_MAX_MEM, 1 << (2 * self.shard_bases)),
+            bucket_sizes: None,
+            spill_dir: None,
+            shard_bases: self.shard_bases,
+            subsample: None,
+            seed: 0,
+            max_records: None,
+            include_contigs: None,
+            exclude_contigs: None,
+            scheme: Scheme::Minimizer,
+            syncmer_s: DEFAULT_SYNCMER_S,
+        }
+    }
+
+    fn count_params(&self) -> CountParams {
+        CountParams {
+            k: self.k,
+            w: self.k - self.m + 1,
+            canonical: self.canonical,
+            keep_kmers: false,
+            sort_kmers: false,
+            histo_max: None,
+            min_count: None,
+            max_count: None,
+            scaled: None,
+            counts: false,
+            strands: false,
+            spill_dir: None,
+            method: self.method,
+            map_capacity: DEFAULT_MAP_CAPACITY,
+            minhash: None,
+            seed: 0,
+            spaced: None,
+            gc: None,
+            min_entropy: None,
+            distinct_superkmers: false,
+            palindromes: false,
+            gc_histogram: false,
+            stable: false,
+            concurrent_shards: None,
+        }
+    }
+
+    /// Counts the distinct k-mers of all the `paths` together.
+    pub fn count(&self, paths: &[String]) -> Result<CountResult> {
+        let params = self.collect_params();
+        params.check()?;
+        let pool = ThreadPoolBuilder::new().num_threads(self.threads).build()?;
+        let start = Instant::now();
+        let buckets = collect_superkmers(&params, paths, None, None, None, None)?;
+        let collect_seconds = start.elapsed().as_secs_f64();
+        let start = Instant::now();
+        let shards = pool.install(|| count_shards(buckets, &self.count_params()))?;
+        Ok(CountResult {
+            distinct: shards.iter().map(|s| s.distinct).sum(),
+            total: shards.iter().map(|s| s.total).sum(),
+            superkmers: shards.iter().map(|s| s.superkmers).sum(),
+            collect_seconds,
+            count_seconds: start.elapsed().as_secs_f64(),
+        })
+    }
+
+    /// Lazily yields the distinct k-mers of all the `paths` together (2-bit encoded, first
+    /// base in the low bits, see [`decode`]).
+    /// The shards are expanded one after the other as the iterator advances, so that only the
+    /// super-k-mers and the k-mers of a single shard are in memory at a time.
+    pub fn distinct_kmers(&self, paths: &[String]) -> Result<impl Iterator<Item = KT>> {
+        let params = self.collect_params();
+        params.check()?;
+        let buckets = collect_superkmers(&params, paths, None, None, None, None)?;
+        let params = self.count_params();
+        let kmers = move |v: Bucket| count_shard::<()>(v.into_inner().unwrap(), &params).0;
+        Ok(buckets.into_iter().flat_map(kmers).map(|(kmer, ())| kmer))
+    }
+
+    /// Hands each super-k-mer of the `paths` to `sink` along with its shard, instead of
+    /// counting them, as [`for_each_superkmer`] does with the parameters of this counter:
+    ///
+    /// ```no_run
+    /// use distinct_kmers::{decode_superkmer, DistinctKmerCounter};
+    /// use std::sync::Mutex;
+    ///
+    /// let counter = DistinctKmerCounter::new(31);
+    /// let longest = Mutex::new(Vec::new());
+    /// counter.for_each_superkmer(&["reads.fa.gz".to_string()], &|_shard, skmer| {
+    ///     let mut bases = Vec::new();
+    ///     decode_superkmer(skmer, &mut bases);
+    ///     let mut longest = longest.lock().unwrap();
+    ///     if bases.len() > longest.len() {
+    ///         *longest = bases;
+    ///     }
+    ///     Ok(())
+    /// })?;
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn for_each_superkmer(&self, paths: &[String], sink: &SuperkmerSink<'_>) -> Result<()> {
+        let params = self.collect_params();
+        params.check()?;
+        for_each_superkmer(&params, paths, None, sink, None)
+    }
+
+    /// Keeps the distinct k-mers of all the `paths` together in memory, in one set per shard,
+    /// for set operations between inputs counted with the same counter: a k-mer can only be
+    /// in the same shard of two of them.
+    pub fn shard_sets(&self, paths: &[String]) -> Result<ShardSets> {
+        let params = self.collect_params();
+        params.check()?;
+        let pool = ThreadPoolBuilder::new().num_threads(self.threads).build()?;
+        let buckets = collect_superkmers(&params, paths, None, None, None, None)?;
+        let count_params = self.count_params();
+        let shards: Vec<HashSet<KT, FxBuildHasher>> = pool.install(|| {
+            buckets
+                .into_par_iter()
+                .map(|v| {
+                    let (kmers, _) = count_shard::<()>(v.into_inner().unwrap(), &count_params);
+                    kmers.into_keys().collect()
+                })
+                .collect()
+        });
+        Ok(ShardSets {
+            shards,
+            shard_bases: self.shard_bases,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2-bit encoding of `bases`, first base in the low bits, as read by [`decode`].
+    fn encode(bases: &[u8]) -> KT {
+        let code = |c: u8| ((c >> 1) & 3) as KT;
+        bases.iter().rev().fold(0, |kmer, &c| (kmer << 2) | code(c))
+    }
+
+    fn naive_revcomp(bases: &[u8]) -> Vec<u8> {
+        let complement = |c: &u8| match c {
+            b'A' => b'T',
+            b'C' => b'G',
+            b'G' => b'C',
+            b'T' => b'A',
+            _ => panic!("not a base: {c}"),
+        };
+        bases.iter().rev().map(complement).collect()
+    }
+
+    /// Pseudo-random bases.
+    fn random_bases(len: usize, seed: u64) -> Vec<u8> {
+        let base = |i: u64| b"ACGT"[(fmix64((seed << 32) | i) & 3) as usize];
+        (0..len as u64).map(base).collect()
+    }
+
+    /// A FASTA file of the `records`.
+    fn fasta(records: &[&[u8]]) -> Vec<u8> {
+        let mut fasta = vec![];
+        for (i, seq) in records.iter().enumerate() {
+            writeln!(fasta, ">r{i}").unwrap();
+            fasta.extend_from_slice(seq);
+            fasta.push(b'\n');
+        }
+        fasta
+    }
+
+    /// Writes `contents` to a new file of the temporary directory, named to end in `suffix`.
+    fn temp_file(contents: &[u8], suffix: &str) -> String {
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        let n = FILES.fetch_add(1, Ordering::Relaxed);
+        let name = format!("distinct-kmers-test-{}-{n}{suffix}", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    /// The parameters of a default counter of `k`-mers with minimizers of size `m`.
+    fn params(k: usize, m: usize) -> (CollectParams, CountParams) {
+        let counter = DistinctKmerCounter {
+            m,
+            ..DistinctKmerCounter::new(k)
+        };
+        (counter.collect_params(), counter.count_params())
+    }
+
+    /// Number of distinct k-mers of the `fasta` file contents.
+    fn distinct(collect: &CollectParams, count: &CountParams, fasta: &[u8]) -> usize {
+        collect.check().unwrap();
+        let paths = [temp_file(fasta, ".fa")];
+        let buckets = collect_superkmers(collect, &paths, None, None, None, None);
+        std::fs::remove_file(&paths[0]).unwrap();
+        let shards = count_shards(buckets.unwrap(), count).unwrap();
+        shards.iter().map(|s| s.distinct).sum()
+    }
+
+    /// Number of distinct k-mers of the `records`, leaving out those with bases other than ACGT.
+    fn naive_distinct(records: &[&[u8]], k: usize, canonical: bool) -> usize {
+        let mut kmers = HashSet::new();
+        let is_dna = |kmer: &&[u8]| kmer.iter().all(|c| b"ACGT".contains(c));
+        for kmer in records.iter().flat_map(|seq| seq.windows(k)).filter(is_dna) {
+            let kmer = kmer.to_vec();
+            let rc = naive_revcomp(&kmer);
+            kmers.insert(if canonical { rc.min(kmer) } else { kmer });
+        }
+        kmers.len()
+    }
+
+    #[test]
+    fn revcomp_reverses_and_complements_the_bases() {
+        assert_eq!(revcomp(encode(b"AAC"), 3), encode(b"GTT"));
+        assert_eq!(revcomp(encode(b"ACG"), 3), encode(b"CGT"));
+        let bases = b"ACGTTGCAAGGCTTACCGATCGATGGCATCAGTCCA";
+        for k in [1, 2, 3, 4, 16, 31, 32] {
+            let kmer = &bases[..k];
+            let rc = revcomp(encode(kmer), k);
+            assert_eq!(rc, encode(&naive_revcomp(kmer)), "k = {k}");
+            let mut decoded = vec![];
+            decode(rc, k, &mut decoded);
+            assert_eq!(decoded, naive_revcomp(kmer), "k = {k}");
+        }
+    }
+    #[test]
+    fn both_strands_give_the_same_count() {
+        let (collect, count) = params(31, 21);
+        let seq = random_bases(2000, 1);
+        let rc = naive_revcomp(&seq);
+        let forward = distinct(&collect, &count, &fasta(&[&seq]));
+        assert_eq!(forward, naive_distinct(&[&seq], 31, true));
+        assert_eq!(distinct(&collect, &count, &fasta(&[&rc])), forward);
+        assert_eq!(distinct(&collect, &count, &fasta(&[&seq, &rc])), forward);
+    }
+
+    #[test]
+    fn canonical_even_k_needs_forward_only_or_syncmers() {
+        let (mut collect, _) = params(32, 21);
+        assert!(collect.check().is_err());
+        collect.canonical = false;
+        assert!(collect.check().is_ok());
+        collect.canonical = true;
+        collect.scheme = Scheme::Syncmer;
+        assert!(collect.check().is_ok());
+    }
+
+    #[test]
+    fn rna_counts_as_dna() {
+        let (mut collect, count) = params(31, 21);
+        collect.rna = true;
+        let dna = random_bases(1000, 2);
+        let rna: Vec<u8> = dna
+            .iter()
+            .map(|&c| if c == b'T' { b'U' } else { c })
+            .collect();
+        let expected = naive_distinct(&[&dna], 31, true);
+        assert_eq!(distinct(&collect, &count, &fasta(&[&rna])), expected);
+        assert_eq!(distinct(&collect, &count, &fasta(&[&dna])), expected);
+    }
+
+    #[test]
+    fn ambiguity_codes_break_the_kmers() {
+        let (collect, count) = params(31, 21);
+        let (a, b, c) = (
+            random_bases(300, 3),
+            random_bases(200, 4),
+            random_bases(100, 5),
+        );
+        let seq = [&a[..], b"R", &b, b"YY", &c].concat();
+        let expected = naive_distinct(&[&a, &b, &c], 31, true);
+        assert_eq!(distinct(&collect, &count, &fasta(&[&seq])), expected);
+    }
+
+    #[test]
+    fn lowercase_counts_unless_masked() {
+        let (mut collect, count) = params(31, 21);
+        let (a, b, c) = (
+            random_bases(300, 6),
+            random_bases(200, 7),
+            random_bases(100, 8),
+        );
+        let upper = [&a[..], &b, &c].concat();
+        let seq = [&a[..], &b.to_ascii_lowercase(), &c].concat();
+        let expected = naive_distinct(&[&upper], 31, true);
+        assert_eq!(distinct(&collect, &count, &fasta(&[&seq])), expected);
+        assert_eq!(
+            distinct(&collect, &count, &fasta(&[&seq.to_ascii_lowercase()])),
+            expected
+        );
+        collect.skip_masked = true;
+        let expected = naive_distinct(&[&a, &c], 31, true);
+        assert_eq!(distinct(&collect, &count, &fasta(&[&seq])), expected);
+    }
+
+    #[test]
+    fn scaled_estimate_is_close_to_the_count() {
+        let (collect, mut count) = params(31, 21);
+        let seq = random_bases(200_000, 9);
+        let exact = naive_distinct(&[&seq], 31, true);
+        count.scaled = Some(100);
+        let estimate = 100 * distinct(&collect, &count, &fasta(&[&seq]));
+        let error = (estimate as f64 - exact as f64).abs() / exact as f64;
+        assert!(
+            error < 0.1,
+            "estimated {estimate} distinct k-mers, not {exact}"
+        );
+    }
+
+    #[test]
+    fn longest_superkmers_keep_their_length() {
+        // with m = 1, a super-k-mer can hold up to w = k k-mers, 2k - 1 bases
+        let (collect, count) = params(31, 1);
+        let seq = random_bases(5000, 10);
+        let expected = naive_distinct(&[&seq], 31, true);
+        assert_eq!(distinct(&collect, &count, &fasta(&[&seq])), expected);
+        let (mut collect, _) = params(MAX_K, 1);
+        collect.canonical = false;
+        assert!(2 * MAX_K - 1 > MAX_SKLEN && collect.check().is_err());
+    }
+
+    #[test]
+    fn short_minimizers_select_the_shard_by_their_own_bases() {
+        let (collect, count) = params(31, 5);
+        let seq = random_bases(5000, 11);
+        let paths = [temp_file(&fasta(&[&seq]), ".fa")];
+        let buckets = collect_superkmers(&collect, &paths, None, None, None, None);
+        std::fs::remove_file(&paths[0]).unwrap();
+        let buckets = buckets.unwrap();
+        // the keys only have the 5 bases of the minimizers, not 8
+        let used = |b: &Bucket| !b.lock().unwrap().is_empty();
+        assert!(!buckets[1 << (2 * 5)..].iter().any(used));
+        let shards = count_shards(buckets, &count).unwrap();
+        let distinct: usize = shards.iter().map(|s| s.distinct).sum();
+        assert_eq!(distinct, naive_distinct(&[&seq], 31, true));
+    }
+
+    #[test]
+    fn syncmers_and_minimizers_count_the_same() {
+        let (mut collect, count) = params(31, 21);
+        let seq = random_bases(5000, 12);
+        let minimizers = distinct(&collect, &count, &fasta(&[&seq]));
+        collect.scheme = Scheme::Syncmer;
+        assert_eq!(distinct(&collect, &count, &fasta(&[&seq])), minimizers);
+        assert_eq!(minimizers, naive_distinct(&[&seq], 31, true));
+    }
+
+    #[test]
+    fn contiguous_spaced_seed_counts_the_kmers() {
+        let (collect, mut count) = params(31, 21);
+        let seq = random_bases(5000, 13);
+        let contiguous = distinct(&collect, &count, &fasta(&[&seq]));
+        count.spaced = Some((0..31).collect());
+        assert_eq!(distinct(&collect, &count, &fasta(&[&seq])), contiguous);
+    }
+
+    #[test]
+    fn sorted_kmers_do_not_depend_on_the_threads() {
+        let seq = random_bases(20_000, 14);
+        let paths = [temp_file(&fasta(&[&seq]), ".fa")];
+        let sorted_dump = |threads| {
+            let counter = DistinctKmerCounter {
+                threads,
+                ..DistinctKmerCounter::new(31)
+            };
+            let collect = counter.collect_params();
+            let count = CountParams {
+                keep_kmers: true,
+                sort_kmers: true,
+                ..counter.count_params()
+            };
+            let pool = ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let shards = pool.install(|| {
+                let buckets = collect_superkmers(&collect, &paths, None, None, None, None);
+                count_shards(buckets.unwrap(), &count).unwrap()
+            });
+            let mut dump = vec![];
+            for shard in &shards {
+                assert!(shard.kmers.is_sorted());
+                for &kmer in &shard.kmers {
+                    decode(kmer, 31, &mut dump);
+                    dump.push(b'\n');
+                }
+            }
+            dump
+        };
+        let dump = sorted_dump(1);
+        assert_eq!(dump.len(), 32 * naive_distinct(&[&seq], 31, true));
+        assert!(sorted_dump(16) == dump);
+        std::fs::remove_file(&paths[0]).unwrap();
+    }
+
+    #[test]
+    fn empty_and_all_n_inputs_have_no_kmers() {
+        let (collect, count) = params(31, 21);
+        let all_n = fasta(&[&[b'N'; 1000], b"NNNN"]);
+        for contents in [&b""[..], &all_n] {
+            let paths = [temp_file(contents, ".fa")];
+            let buckets = collect_superkmers(&collect, &paths, None, None, None, None);
+            std::fs::remove_file(&paths[0]).unwrap();
+            let buckets = buckets.unwrap();
+            // no bucket got a super-k-mer, so none of them reserved its capacity
+            assert!(buckets.iter().all(|b| b.lock().unwrap().capacity() == 0));
+            let shards = count_shards(buckets, &count).unwrap();
+            assert_eq!(shards.iter().map(|s| s.distinct).sum::<usize>(), 0);
+        }
+    }
+
+    #[test]
+    fn kmers_stop_at_the_n_runs() {
+        let (collect, count) = params(31, 21);
+        // segments of exactly k bases only have the k-mers right before and right after the N
+        let (a, b) = (random_bases(31, 15), random_bases(31, 16));
+        for n in [&b"N"[..], b"NNNNNNNNNN"] {
+            let seq = [&a[..], n, &b].concat();
+            assert_eq!(distinct(&collect, &count, &fasta(&[&seq])), 2);
+        }
+        // and a base short of it, none
+        let seq = [&a[1..], b"N", &b[1..]].concat();
+        assert_eq!(distinct(&collect, &count, &fasta(&[&seq])), 0);
+        let (a, b) = (random_bases(200, 17), random_bases(100, 18));
+        let seq = [&a[..], b"NNN", &b].concat();
+        let expected = naive_distinct(&[&a, &b], 31, true);
+        assert_eq!(expected, 170 + 70);
+        assert_eq!(distinct(&collect, &count, &fasta(&[&seq])), expected);
+    }
+
+    #[test]
+    fn compressed_inputs_count_as_plain() {
+        use niffler::send::compression::Format;
+        let (collect, count) = params(31, 21);
+        let plain = fasta(&[&random_bases(5000, 19)]);
+        let expected = distinct(&collect, &count, &plain);
+        for (format, suffix) in [(Format::Bzip, ".fa.bz2"), (Format::Lzma, ".fa.xz")] {
+            let mut compressed = vec![];
+            let mut writer =
+                niffler::send::get_writer(Box::new(&mut compressed), format, niffler::Level::One)
+                    .unwrap();
+            writer.write_all(&plain).unwrap();
+            drop(writer);
+            let paths = [temp_file(&compressed, suffix)];
+            let buckets = collect_superkmers(&collect, &paths, None, None, None, None);
+            std::fs::remove_file(&paths[0]).unwrap();
+            let shards = count_shards(buckets.unwrap(), &count).unwrap();
+            let distinct: usize = shards.iter().map(|s| s.distinct).sum();
+            assert_eq!(distinct, expected, "{suffix}");
+        }
+    }
+
+    #[test]
+    fn chunks_do_not_change_the_counts() {
+        let (mut collect, count) = params(31, 21);
+        let records = [random_bases(2000, 20), random_bases(500, 21)];
+        let records: Vec<&[u8]> = records.iter().map(Vec::as_slice).collect();
+        let paths = [temp_file(&fasta(&records), ".fa")];
+        let mut counts = vec![];
+        for chunk_bases in [None, Some(31 + 10)] {
+            collect.chunk_bases = chunk_bases;
+            collect.check().unwrap();
+            let buckets = collect_superkmers(&collect, &paths, None, None, None, None);
+            let shards = count_shards(buckets.unwrap(), &count).unwrap();
+            let distinct: usize = shards.iter().map(|s| s.distinct).sum();
+            let total: usize = shards.iter().map(|s| s.total).sum();
+            counts.push((distinct, total));
+        }
+        std::fs::remove_file(&paths[0]).unwrap();
+        let expected = (naive_distinct(&records, 31, true), 2000 - 30 + 500 - 30);
+        assert_eq!(counts, [expected, expected]);
+    }
+
+    #[test]
+    fn upper_only_leaves_out_the_masked_kmers() {
+        let (mut collect, count) = params(31, 21);
+        collect.upper_only = true;
+        let runs = [
+            random_bases(300, 22),
+            random_bases(200, 23),
+            random_bases(100, 24),
+            random_bases(10, 25),
+            random_bases(150, 26),
+        ];
+        let lower = |run: &Vec<u8>| run.to_ascii_lowercase();
+        let seq = [
+            &runs[0][..],
+            &lower(&runs[1]),
+            &runs[2],
+            &lower(&runs[3]),
+            &runs[4],
+        ]
+        .concat();
+        let expected = naive_distinct(&[&runs[0], &runs[2], &runs[4]], 31, true);
+        assert_eq!(distinct(&collect, &count, &fasta(&[&seq])), expected);
+    }
+
+    #[test]
+    fn normalized_case_counts_as_uppercase() {
+        let (mut collect, count) = params(31, 21);
+        collect.normalize_case = true;
+        let upper = random_bases(2000, 27);
+        // alternating lower and upper stretches of various lengths
+        let mixed: Vec<u8> = upper
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                if i % 97 < 40 {
+                    c.to_ascii_lowercase()
+                } else {
+                    *c
+                }
+            })
+            .collect();
+        let expected = distinct(&collect, &count, &fasta(&[&upper]));
+        assert_eq!(expected, naive_distinct(&[&upper], 31, true));
+        assert_eq!(distinct(&collect, &count, &fasta(&[&mixed])), expected);
+    }
+
+    #[test]
+    fn shard_of_finds_the_kmers() {
+        // with m = k, the minimizer of a canonical k-mer is the k-mer itself
+        let counter = DistinctKmerCounter {
+            m: 31,
+            ..DistinctKmerCounter::new(31)
+        };
+        let seq = random_bases(5000, 28);
+        let paths = [temp_file(&fasta(&[&seq]), ".fa")];
+        let sets = counter.shard_sets(&paths).unwrap();
+        let kmers: Vec<KT> = counter.distinct_kmers(&paths).unwrap().collect();
+        std::fs::remove_file(&paths[0]).unwrap();
+        assert_eq!(kmers.len(), naive_distinct(&[&seq], 31, true));
+        for kmer in &kmers {
+            assert!(sets.shards[sets.shard_of(*kmer)].contains(kmer));
+        }
+        let sizes: usize = sets.shards.iter().map(HashSet::len).sum();
+        assert_eq!(sizes, kmers.len());
+    }
+}
@@ -1,239 +1,1677 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{bail, ensure, Context, Result};
+use clap::{Parser, ValueEnum};
 use core::array::from_fn;
-use niffler::send::from_path;
-use packed_seq::{PackedSeqVec, Seq, SeqVec};
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use distinct_kmers::{
+    auto_m, bucket_cap, check_inputs, collect_superkmers, compare_shards, count_shards,
+    count_with_bloom, decode, estimate_distinct, expand_glob, index, kff, log, query_file,
+    read_file_list, set_verbosity, size_buckets, write_bed, Bucket, CollectParams, CountMethod,
+    CountParams, DistinctKmerCounter, KmerSet, MinimizerCounts, RecordWriter, Scheme, ShardCount,
+    DEFAULT_MAP_CAPACITY, DEFAULT_MAX_MEM, DEFAULT_SHARD_BASES, DEFAULT_SYNCMER_S, KT, SKT,
+};
+use niffler::send::compression::Format;
+use niffler::Level;
 use rayon::{current_num_threads, ThreadPoolBuilder};
-use regex::bytes::{Regex, RegexBuilder};
+use regex::bytes::Regex;
 use rustc_hash::FxBuildHasher;
-use seq_io::{fasta, fastq};
-use seq_io_parallel::{MinimalRefRecord, ParallelProcessor, ParallelReader};
-use simd_minimizers::minimizer_and_superkmer_positions;
-use std::collections::HashSet;
-use std::path::Path;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::iter::repeat;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 use std::time::Instant;
-use std::fs::File;
-use std::io::{self, BufRead};
-
-type KT = u64;
-type SKT = u128; // together as one
-type Bucket = Mutex<Vec<SKT>>;
-
-const SHARD_BASES: usize = 8;
-const SHARDS: usize = 1 << (2 * SHARD_BASES);
-const SKLEN_BITS: usize = 6;
-const SKLEN_MASK: SKT = (1 << SKLEN_BITS) - 1;
-const BUCKET_CAP: usize = (8 << 30) / (SHARDS * SKT::BITS as usize);
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Input file (FASTA, possibly compressed)
+    /// Input files (FASTA, possibly compressed), `-` for stdin, `@FILE` for a file of files,
+    /// or directories of sequence files
+    #[arg(required_unless_present_any = ["glob", "load_index"], num_args = 1..)]
+    input: Vec<String>,
+    /// Also count the files matching this pattern, e.g. `reads/*.fastq.gz` (can be repeated)
+    #[arg(long)]
+    glob: Vec<String>,
+    /// Mates of the (single) input for paired-end reads
+    #[arg(long, conflicts_with = "glob")]
+    input2: Option<String>,
+    /// The reads are interleaved pairs: warn if their mates (/1 and /2) do not pair up
+    #[arg(long, conflicts_with = "input2")]
+    interleaved: bool,
+    /// Fail, rather than warn, when an input is given twice (e.g. in an @FILE list)
+    #[arg(long)]
+    strict: bool,
+    /// Also look for sequence files in the subdirectories of input directories
     #[arg(short, long)]
-    input: String,
-    /// K-mer size (up to 32)
+    recursive: bool,
+    /// K-mer size (up to 32, or 64 with the `wide` feature)
     #[arg(short)]
     k: usize,
+    /// Count spaced seeds of this pattern instead of contiguous k-mers, e.g. `1101011`: the
+    /// bases at its 1s (k of them) are kept from each window of its length
+    #[arg(long, conflicts_with = "per_record")]
+    spaced: Option<String>,
     /// Minimizer size
     #[arg(short, default_value_t = 21)]
     m: usize,
+    /// Choose the minimizer size from k: 21, or k if smaller, or larger for the super-k-mers
+    /// to fit in a word
+    #[arg(long, conflicts_with = "m")]
+    auto_m: bool,
+    /// How to cut the sequences into super-k-mers (the counts are the same)
+    #[arg(long, value_enum, default_value_t = Scheme::Minimizer)]
+    scheme: Scheme,
+    /// Length of the s-mers defining the closed syncmers of size m, with --scheme syncmer
+    #[arg(long, default_value_t = DEFAULT_SYNCMER_S)]
+    syncmer_s: usize,
     /// Number of threads [default: all]
     #[arg(short, long)]
     threads: Option<usize>,
-    /// Input is FASTQ
+    /// Input is FASTQ, an error for FASTA files (the format of each file is detected otherwise)
     #[arg(short, long)]
     fastq: bool,
+    /// Skip the records shorter than this
+    #[arg(long, default_value_t = 0)]
+    min_length: usize,
+    /// Only count the records whose name (the first word of their header) matches this regex
+    #[arg(long, value_parser = Regex::new)]
+    include_contigs: Option<Regex>,
+    /// Skip the records whose name matches this regex
+    #[arg(long, value_parser = Regex::new)]
+    exclude_contigs: Option<Regex>,
+    /// Only count a fraction of the records, chosen by a hash of their id (mates are kept
+    /// together when they share an id)
+    #[arg(long, value_parser = parse_fraction)]
+    subsample: Option<f64>,
+    /// Seed of the hashes choosing the subsampled records, the bucket of each minimizer and the
+    /// k-mers kept by --scaled and --minhash: only the counts of --scaled and --subsample
+    /// depend on it
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Stop after this many records over all the inputs, e.g. for quick trials on large files
+    /// (the file being read is still parsed to its end, skipping the other records)
+    #[arg(long)]
+    max_records: Option<usize>,
+    /// Only read the inputs to check that they can be opened, decompressed and parsed,
+    /// reporting their numbers of records and bases, without collecting any k-mer
+    #[arg(long)]
+    check: bool,
+    /// Treat soft-masked (lowercase) bases as breaks, like N, to only count unmasked regions
+    #[arg(long)]
+    skip_masked: bool,
+    /// Leave out the k-mers overlapping soft-masked (lowercase) bases, without breaking the
+    /// sequences: the minimizers and super-k-mers are those of the whole sequences
+    #[arg(long, conflicts_with = "skip_masked")]
+    upper_only: bool,
+    /// Upper-case the bases before packing them. Lowercase bases are counted like uppercase
+    /// ones anyway, so this only turns off --skip-masked and --upper-only, e.g. for inputs
+    /// written in lowercase by their producer rather than soft-masked
+    #[arg(long)]
+    normalize_case: bool,
+    /// Compute the minimizers of long records in chunks of this many bases, overlapping by
+    /// k - 1 bases, to bound the memory of each thread (e.g. 64000000 for whole chromosomes):
+    /// the counts do not change, only the super-k-mers at the ends of the chunks
+    #[arg(long)]
+    chunk_bases: Option<usize>,
+    /// Trim the bases of quality below this from both ends of FASTQ reads
+    #[arg(long)]
+    trim_qual: Option<u8>,
+    /// Skip the FASTQ reads whose mean quality is below this
+    #[arg(long)]
+    min_mean_qual: Option<u8>,
+    /// Offset of the FASTQ quality scores
+    #[arg(long, default_value_t = 33)]
+    trim_phred_offset: u8,
+    /// Count k-mers as read instead of canonical k-mers (strand-specific data)
+    #[arg(long)]
+    forward_only: bool,
+    /// Report how many k-mers were seen only forward, only reverse, or on both strands
+    #[arg(long, conflicts_with = "forward_only")]
+    strand_report: bool,
+    /// Input is RNA (U is read as T)
+    #[arg(long)]
+    rna: bool,
+    /// Write the distinct k-mers to this file, one per line (`-` for stdout)
+    #[arg(short, long)]
+    output: Option<String>,
+    /// Write the distinct k-mers to this file as FASTA records (gzipped if it ends in .gz)
+    #[arg(long)]
+    fasta_out: Option<String>,
+    /// Write the distinct k-mers to this file as little-endian integers, sorted within each shard
+    #[arg(long)]
+    binary_out: Option<String>,
+    /// Write the presence bitset of the k-mers to this file: bit i of the 4^k bits, in
+    /// little-endian 64-bit words, is set when k-mer i is present (k up to 16)
+    #[arg(long)]
+    bitset_out: Option<String>,
+    /// Write to this file the BED intervals of the positions of the --reference at which starts
+    /// a unique k-mer, occurring exactly once in the inputs
+    #[arg(long, requires = "reference", conflicts_with_all = ["spaced", "scaled"])]
+    unique_bed: Option<String>,
+    /// Reference (FASTA or FASTQ, possibly compressed) along which --unique-bed is written
+    #[arg(long, requires = "unique_bed")]
+    reference: Option<String>,
+    /// Write the distinct k-mers to this file in the K-mer File Format (KFF)
+    #[arg(long)]
+    kff_out: Option<String>,
+    /// Print `<kmer> <count>` lines to stdout, like `jellyfish dump -c`
+    #[arg(long)]
+    dump: bool,
+    /// Column separator of --dump
+    #[arg(long, default_value = " ")]
+    dump_sep: String,
+    /// Write the k-mers of -o, --fasta-out, --binary-out, --kff-out, --dump and --diff-out in
+    /// increasing order of their encoding, rather than shard by shard, so that the output does
+    /// not depend on the number of threads
+    #[arg(long)]
+    sorted: bool,
+    /// Sort the super-k-mers of each bucket before counting them, so that the k-mers are
+    /// written out in the same order from run to run, still shard by shard: cheaper than
+    /// --sorted, which makes it useless
+    #[arg(long)]
+    stable: bool,
+    /// Write the k-mer abundance histogram to this file, as `<frequency>\t<k-mers>` lines
+    #[arg(long)]
+    histogram: Option<String>,
+    /// Last bin of the histogram, counting all k-mers that occur at least this many times
+    #[arg(
+        long,
+        default_value_t = 10000,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    histo_max: usize,
+    /// Write the k-mer spectrum to this TSV file: frequency, distinct k-mers at that frequency,
+    /// cumulative distinct k-mers and cumulative occurrences, up to --histo-max
+    #[arg(long)]
+    spectrum: Option<String>,
+    /// Estimate the sequencing depth and genome size from the main peak of the histogram
+    #[arg(long)]
+    estimate_coverage: bool,
+    /// Lowest frequency considered for the coverage peak, to skip the error peak
+    #[arg(long, default_value_t = 2)]
+    peak_min_freq: usize,
+    /// Count the occurrences of each k-mer (written along with the k-mers by --kff-out)
+    #[arg(short, long)]
+    counts: bool,
+    /// Only keep (and write out) the k-mers occurring at least this many times
+    #[arg(long)]
+    min_count: Option<u32>,
+    /// Only keep (and write out) the k-mers occurring at most this many times
+    #[arg(long)]
+    max_count: Option<u32>,
+    /// Only count the k-mers with at least this fraction of G and C bases
+    #[arg(long, value_parser = parse_fraction, conflicts_with = "per_record")]
+    min_gc: Option<f64>,
+    /// Only count the k-mers with at most this fraction of G and C bases
+    #[arg(long, value_parser = parse_fraction, conflicts_with = "per_record")]
+    max_gc: Option<f64>,
+    /// Report the number of distinct k-mers per number of G and C bases
+    #[arg(long)]
+    gc_summary: bool,
+    /// Only count the k-mers whose base composition has at least this Shannon entropy, in
+    /// bits (0 for a homopolymer, up to 2), to leave out the low-complexity ones
+    #[arg(long, conflicts_with = "per_record")]
+    min_entropy: Option<f64>,
+    /// Report the number of distinct k-mers that are their own reverse complement (only
+    /// possible for even k)
+    #[arg(long, conflicts_with = "spaced")]
+    palindromes: bool,
+    /// Report the number of k-mers occurring exactly once, in all and per shard
+    #[arg(long)]
+    singletons: bool,
+    /// Only count the k-mers whose hash is below 2^64 / SCALED and scale the count back up
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    scaled: Option<u64>,
+    /// Count each record on its own, writing `<record id>\t<distinct k-mers>` lines to this file
+    #[arg(long)]
+    per_record: Option<String>,
+    /// With --per-record, count the k-mers of each window of this many bases of the records
+    /// instead, writing `<record id>\t<start>\t<end>\t<distinct k-mers>` lines
+    #[arg(long, requires = "per_record")]
+    window: Option<usize>,
+    /// Start a window every this many bases [default: the window size]
+    #[arg(long, requires = "window")]
+    step: Option<usize>,
+    /// Also count the k-mers of each file listed by an `@FILE` input on its own, and report the
+    /// distinct k-mers of each file in a table (each file is read twice)
+    #[arg(long, conflicts_with = "input2")]
+    per_file_counts: bool,
+    /// Also report the number of k-mers present in every file listed by the `@FILE` inputs,
+    /// e.g. the core k-mers of the genomes of a pan-genome (each file is read twice)
+    #[arg(long, conflicts_with = "input2")]
+    core: bool,
+    /// Write the number of distinct k-mers of the files listed by the `@FILE` inputs taken
+    /// together, after each of them in the listed order, to this TSV file (each file is read
+    /// twice)
+    #[arg(long, conflicts_with = "input2")]
+    accumulation_curve: Option<String>,
+    /// Also report the number of distinct super-k-mers, a proxy for the size of a compacted
+    /// de Bruijn graph
+    #[arg(long)]
+    count_superkmers: bool,
+    /// Report the number of distinct minimizers and of super-k-mers per minimizer
+    #[arg(long)]
+    minimizer_stats: bool,
+    /// Report the spread of the bucket sizes and the largest buckets, to tune --shard-bases
+    #[arg(long)]
+    shard_stats: bool,
+    /// Number of minimizer bases selecting the bucket of a super-k-mer: there are 4^N buckets.
+    /// More buckets lock less often when collecting and give smaller hash maps when counting,
+    /// but cost more allocations
+    #[arg(long, default_value_t = DEFAULT_SHARD_BASES)]
+    shard_bases: usize,
+    /// Memory to reserve up front for the super-k-mers, in GiB
+    #[arg(long, default_value_t = DEFAULT_MAX_MEM)]
+    max_mem: f64,
+    /// Read the inputs twice: first only to count the super-k-mers of each bucket, then to
+    /// fill buckets of exactly that size instead of reserving --max-mem (not for stdin)
+    #[arg(long, conflicts_with_all = ["spill_dir", "per_record"])]
+    two_pass: bool,
+    /// Reserve nothing up front: the buckets start empty and grow as needed, which takes less
+    /// memory when most buckets get fewer super-k-mers than their --max-mem share, at the cost
+    /// of more reallocations (not with --spill-dir, which spills past the --max-mem share)
+    #[arg(long, conflicts_with_all = ["max_mem", "two_pass", "spill_dir"])]
+    no_prealloc: bool,
+    /// Only estimate the number of distinct k-mers, with HyperLogLog sketches
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "output", "fasta_out", "binary_out", "bitset_out", "kff_out", "dump", "histogram",
+            "spectrum"
+        ]
+    )]
+    estimate: bool,
+    /// Sketches have 2^P registers, for an expected error of 104/sqrt(2^P) %
+    #[arg(long, default_value_t = 14, value_parser = clap::value_parser!(u32).range(4..=18))]
+    hll_precision: u32,
+    /// Only approximate the number of distinct k-mers, with a Bloom filter of this many bits
+    /// per bucket: the count is slightly low, more so with fewer bits
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "estimate", "output", "fasta_out", "binary_out", "bitset_out", "kff_out", "dump",
+            "histogram", "spectrum"
+        ]
+    )]
+    bloom_bits: Option<usize>,
+    /// Keep a MinHash sketch of the N smallest k-mer hashes, to compare samples later
+    #[arg(
+        long,
+        requires = "minhash_out",
+        conflicts_with_all = ["estimate", "bloom_bits"],
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    minhash: Option<usize>,
+    /// Write the MinHash sketch to this file
+    #[arg(long, requires = "minhash")]
+    minhash_out: Option<String>,
+    /// Compare the k-mers of the input with those of this file: report the Jaccard index and
+    /// the containment of each set in the other
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "estimate", "bloom_bits", "minhash", "per_record", "spill_dir", "output", "fasta_out",
+            "binary_out", "bitset_out", "kff_out", "dump", "histogram", "spectrum"
+        ]
+    )]
+    compare: Option<String>,
+    /// Report the number of k-mers only in the input and only in this file
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "compare", "estimate", "bloom_bits", "minhash", "per_record", "spill_dir", "output",
+            "fasta_out", "binary_out", "bitset_out", "kff_out", "dump", "histogram", "spectrum"
+        ]
+    )]
+    diff: Option<String>,
+    /// Write the k-mers only in the input (and not in the --diff file) to this file
+    #[arg(long, requires = "diff")]
+    diff_out: Option<String>,
+    /// How to find the distinct k-mers of each bucket
+    #[arg(long, value_enum, default_value_t = CountMethod::Hash)]
+    count_method: CountMethod,
+    /// Count at most this many shards at once, rather than one per thread, to lower the peak
+    /// memory of the counting: only that many hash maps are then in memory together.
+    /// The collection still uses all the --threads. --count-threads is the same option: the
+    /// counting pool has one thread per shard counted at once
+    #[arg(
+        long,
+        visible_alias = "count-threads",
+        value_name = "SHARDS",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    low_mem_count: Option<usize>,
+    /// Most entries to reserve up front in the hash map of a shard (it grows past it as needed)
+    #[arg(long, default_value_t = DEFAULT_MAP_CAPACITY)]
+    map_capacity: usize,
+    /// Move the buckets that outgrow --max-mem to files in this directory
+    #[arg(long)]
+    spill_dir: Option<PathBuf>,
+    /// Show the progress of reading each input file (when stderr is a terminal)
+    #[arg(long)]
+    progress: bool,
+    /// Log more: also the time taken by each input file, and by each shard with -vv
+    #[arg(short, long, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+    /// Only log the results and the warnings, not the progress of the run
+    #[arg(short, long)]
+    quiet: bool,
+    /// Report the peak memory (resident set size) of the process at the end of the collection
+    /// and at the end of the counting (Linux only)
+    #[arg(long)]
+    report_mem: bool,
+    /// Write a JSON summary of the run to this file, with the effective parameters
+    #[arg(long)]
+    json: Option<String>,
+    /// Print the effective parameters of the run, derived ones included, at startup
+    #[arg(long)]
+    info: bool,
+    /// Write the numbers of total and distinct k-mers to this file (stdout if no file is given),
+    /// as `total_kmers=<N>` and `distinct_kmers=<N>` lines, away from the logs on stderr; not
+    /// with the modes that stop short of the exact count
+    #[arg(
+        long,
+        num_args = 0..=1,
+        default_missing_value = "-",
+        conflicts_with_all = ["check", "per_record", "compare", "diff", "estimate", "bloom_bits"]
+    )]
+    count_out: Option<String>,
+    /// Save the distinct k-mers to this index, to reuse them with --load-index or `query`
+    #[arg(long)]
+    save_index: Option<String>,
+    /// Read the distinct k-mers from this index instead of counting the inputs: only -o,
+    /// --fasta-out, --binary-out and --kff-out are written, without counts
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "input", "glob", "input2", "save_index", "compare", "diff", "estimate", "bloom_bits",
+            "per_record", "dump", "histogram", "spectrum", "minhash", "counts", "json",
+            "count_out"
+        ]
+    )]
+    load_index: Option<String>,
 }
 
-#[derive(Clone)]
-pub struct SuperkmerCollector<'a> {
+/// Reports, for each record of a query file, the fraction of its k-mers that are in a set
+/// written by `--binary-out`
+#[derive(Parser, Debug)]
+#[command(name = "distinct-kmers query")]
+struct QueryArgs {
+    /// K-mer set written by `--binary-out`
+    set: PathBuf,
+    /// Query file (FASTA, possibly compressed), `-` for stdin
+    query: String,
+    /// K-mer size of the set
+    #[arg(short)]
     k: usize,
-    m: usize,
-    buckets: &'a [Bucket; SHARDS],
-    match_n: &'a Regex,
-    match_newline: &'a Regex,
-    min_pos_vec: Vec<u32>,
-    sk_pos_vec: Vec<u32>,
-}
-
-impl ParallelProcessor for SuperkmerCollector<'_> {
-    fn process_record<'a, Rf: MinimalRefRecord<'a>>(&mut self, record: Rf) -> Result<()> {
-        let w = self.k - self.m + 1;
-        for raw_seq in self
-            .match_n
-            .split(record.ref_seq())
-            .filter(|&s| s.len() >= self.k)
-        {
-            let mut packed_seq = PackedSeqVec::default();
-            for line in self.match_newline.split(raw_seq) {
-                if !line.is_empty() {
-                    packed_seq.push_ascii(line);
-                }
-            }
+    /// The set was counted with --forward-only
+    #[arg(long)]
+    forward_only: bool,
+    /// Number of threads [default: all]
+    #[arg(short, long)]
+    threads: Option<usize>,
+    /// Query is FASTQ (detected otherwise)
+    #[arg(short, long)]
+    fastq: bool,
+    /// Write the `<record id>\t<k-mers in the set>\t<k-mers>\t<fraction>` lines to this file
+    #[arg(short, long, default_value = "-")]
+    output: String,
+}
 
-            // println!("packed seq: {:?}", packed_seq);
-            let len = packed_seq.len();
-            if len >= self.k {
-                self.min_pos_vec.clear();
-                self.min_pos_vec.reserve(len * 5 / 2 / (w + 1));
-                self.sk_pos_vec.clear();
-                self.sk_pos_vec.reserve(len * 5 / 2 / (w + 1));
-                minimizer_and_superkmer_positions(
-                    packed_seq.as_slice(),
-                    self.m,
-                    w,
-                    &mut self.min_pos_vec,
-                    &mut self.sk_pos_vec,
-                );
-                self.min_pos_vec.push(u32::MAX);
-                self.sk_pos_vec.push((len - (self.k - 1)) as u32);
-                let mut min_pos = self.min_pos_vec[0];
-                let mut sk_pos = self.sk_pos_vec[0];
-                for (&next_min_pos, &next_sk_pos) in
-                    self.min_pos_vec.iter().zip(self.sk_pos_vec.iter()).skip(1)
-                {
-                    let shard_range = (min_pos as usize)..(min_pos as usize + SHARD_BASES);
-                    let shard = packed_seq.slice(shard_range).to_word();
-                    let sk_range = (sk_pos as usize)..((next_sk_pos as usize) + self.k - 1);
-                    let sk_mid = (sk_range.start + sk_range.end) / 2;
-                    let left = packed_seq.slice(sk_range.start..sk_mid).to_word() as SKT;
-                    let right = packed_seq.slice(sk_mid..sk_range.end).to_word() as SKT;
-                    let skmer = (((right << (2 * (sk_mid - sk_range.start))) | left) << SKLEN_BITS)
-                        | (sk_range.len() as SKT); // little-endian order
-                    self.buckets[shard].lock().unwrap().push(skmer);
-                    min_pos = next_min_pos;
-                    sk_pos = next_sk_pos;
-                }
-            }
-        }
-        Ok(())
+/// Parses a fraction between 0 and 1.
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(x) if (0. ..=1.).contains(&x) => Ok(x),
+        Ok(_) => Err("the fraction must be between 0 and 1".to_string()),
+        Err(e) => Err(e.to_string()),
     }
 }
 
-
-// The output is wrapped in a Result to allow matching on errors.
-// Returns an Iterator to the Reader of the lines of the file.
-fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
-where P: AsRef<Path>, {
-    let file = File::open(filename)?;
-    Ok(io::BufReader::new(file).lines())
+/// Effective parameters of a run, including the derived ones, for provenance.
+#[derive(Serialize)]
+struct Parameters {
+    k: usize,
+    m: usize,
+    /// minimizer window, in m-mers
+    w: usize,
+    /// bases read for each k-mer, more than k with a spaced seed
+    span: usize,
+    spaced: Option<String>,
+    canonical: bool,
+    threads: usize,
+    /// --low-mem-count, under its --count-threads name
+    count_threads: Option<usize>,
+    scheme: String,
+    syncmer_s: usize,
+    shard_bases: usize,
+    shards: usize,
+    /// super-k-mers reserved per bucket up front
+    bucket_cap: usize,
+    max_mem: f64,
+    count_method: String,
+    seed: u64,
+    min_length: usize,
+    trim_qual: Option<u8>,
+    min_mean_qual: Option<u8>,
+    subsample: Option<f64>,
+    max_records: Option<usize>,
+    min_count: Option<u32>,
+    max_count: Option<u32>,
+    min_gc: Option<f64>,
+    max_gc: Option<f64>,
+    min_entropy: Option<f64>,
+    scaled: Option<u64>,
 }
 
+/// Name of a choice of option, as given on the command line.
+fn value_name(value: impl ValueEnum) -> String {
+    value.to_possible_value().unwrap().get_name().to_string()
+}
 
-fn collect_superkmers<P: AsRef<Path>>(
+/// Machine-readable summary of a run.
+#[derive(Serialize)]
+struct RunSummary {
     k: usize,
     m: usize,
-    path: P,
     threads: usize,
-    is_fastq: bool,
-) -> [Bucket; SHARDS] {
-    let match_n = RegexBuilder::new(r"[N]+")
-        .case_insensitive(true)
-        .unicode(false)
-        .build()
-        .unwrap();
-    let match_newline = RegexBuilder::new(r"[\r\n]+")
-        .unicode(false)
-        .build()
-        .unwrap();
-    let buckets = from_fn(|_| Bucket::new(Vec::with_capacity(BUCKET_CAP)));
-    
-    // if path starts with @ this is a file of file names
-    if path.as_ref().to_string_lossy().starts_with('@') {
-        if let Ok(lines) = read_lines(path) {
-            // Consumes the iterator, returns an (Optional) String
-            for local_path in lines.map_while(Result::ok) {
-                println!("Counting for {}", local_path);
-                let (reader, _) = from_path(local_path).expect("Failed to open input file");
-                let processor = SuperkmerCollector {
-                    k,
-                    m,
-                    buckets: &buckets,
-                    match_n: &match_n,
-                    match_newline: &match_newline,
-                    min_pos_vec: vec![],
-                    sk_pos_vec: vec![],
-                };
-                if is_fastq {
-                    let reader = fastq::Reader::new(reader);
-                    reader.process_parallel(processor, threads).unwrap();
-                }
-                else {
-                    let reader = fasta::Reader::new(reader);
-                    reader.process_parallel(processor, threads).unwrap();
+    inputs: Vec<String>,
+    superkmers: usize,
+    total_kmers: usize,
+    distinct_kmers: usize,
+    collect_seconds: f64,
+    count_seconds: f64,
+    /// memory reserved by the super-k-mer buckets, which dominates the peak
+    estimated_peak_memory_bytes: usize,
+    parameters: Parameters,
+}
+
+/// Creates `path` for writing, `-` meaning stdout, compressing it as its extension says
+/// (`.gz`, `.zst`, `.bz2` or `.xz`).
+fn open_output(path: &str) -> Result<Box<dyn Write + Send>> {
+    if path == "-" {
+        return Ok(Box::new(BufWriter::new(io::stdout())));
+    }
+    let format = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        Some("gz") => Some(Format::Gzip),
+        Some("zst") => Some(Format::Zstd),
+        Some("bz2") => Some(Format::Bzip),
+        Some("xz") => Some(Format::Lzma),
+        _ => None,
+    };
+    let out: Box<dyn Write + Send> = match format {
+        Some(format) => niffler::send::to_path(path, format, Level::Six)?,
+        None => Box::new(File::create(path)?),
+    };
+    Ok(Box::new(BufWriter::new(out)))
+}
+
+/// Writes k-mers (shard by shard, so that the output is deterministic), either one per line
+/// or as FASTA records numbered from 0.
+fn write_kmers(path: &str, kmers: impl Iterator<Item = KT>, k: usize, fasta: bool) -> Result<()> {
+    let mut out = open_output(path)?;
+    let mut record = Vec::with_capacity(k + 24);
+    for (i, kmer) in kmers.enumerate() {
+        record.clear();
+        if fasta {
+            writeln!(record, ">{i}")?;
+        }
+        decode(kmer, k, &mut record);
+        record.push(b'\n');
+        out.write_all(&record)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Prints `<kmer><sep><count>` lines to stdout, like `jellyfish dump -c`.
+fn write_dump(entries: impl Iterator<Item = (KT, u32)>, k: usize, sep: &str) -> Result<()> {
+    let mut out = open_output("-")?;
+    let mut line = Vec::with_capacity(k + 16);
+    for (kmer, count) in entries {
+        line.clear();
+        decode(kmer, k, &mut line);
+        line.extend_from_slice(sep.as_bytes());
+        writeln!(line, "{count}")?;
+        out.write_all(&line)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// The k-mers of a shard with their counts, 0 if they were not counted.
+fn shard_entries(shard: &ShardCount) -> impl Iterator<Item = (KT, u32)> + '_ {
+    let counts = shard.counts.iter().copied().chain(repeat(0));
+    shard.kmers.iter().copied().zip(counts)
+}
+
+/// The k-mers of all the shards with their counts, shard by shard, or in increasing order if
+/// `sorted`, by merging the shards, which must then each be sorted.
+fn entries(shards: &[ShardCount], sorted: bool) -> Box<dyn Iterator<Item = (KT, u32)> + '_> {
+    if !sorted {
+        return Box::new(shards.iter().flat_map(shard_entries));
+    }
+    // a k-mer belongs to a single shard, so there are no duplicates to skip
+    let mut runs: Vec<_> = shards.iter().map(|s| shard_entries(s).peekable()).collect();
+    let mut heads: BinaryHeap<_> = runs
+        .iter_mut()
+        .enumerate()
+        .filter_map(|(i, run)| run.peek().map(|&(kmer, _)| Reverse((kmer, i))))
+        .collect();
+    Box::new(std::iter::from_fn(move || {
+        let Reverse((_, i)) = heads.pop()?;
+        let entry = runs[i].next();
+        if let Some(&(kmer, _)) = runs[i].peek() {
+            heads.push(Reverse((kmer, i)));
+        }
+        entry
+    }))
+}
+
+/// The files listed by the `@FILE` inputs, in order, leaving out the `failed` ones, which could
+/// not be read.
+fn listed_files(inputs: &[String], failed: &[String]) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    for list in inputs.iter().filter(|path| path.starts_with('@')) {
+        files.extend(
+            read_file_list(list)?
+                .into_iter()
+                .filter(|file| !failed.contains(file)),
+        );
+    }
+    Ok(files)
+}
+
+/// The parameters counting a single listed file into fresh buckets: only its distinct k-mers
+/// are needed, whatever their abundance, and they are kept only if `keep_kmers` is set.
+fn single_file_params(params: &CountParams, keep_kmers: bool) -> CountParams {
+    CountParams {
+        keep_kmers,
+        sort_kmers: false,
+        histo_max: None,
+        min_count: None,
+        max_count: None,
+        counts: false,
+        strands: false,
+        minhash: None,
+        distinct_superkmers: false,
+        palindromes: false,
+        gc_histogram: false,
+        stable: false,
+        ..params.clone()
+    }
+}
+
+/// Counts the distinct k-mers of each file listed by the `@FILE` inputs on its own, into fresh
+/// buckets, and prints them in a table along with their share of the `distinct` k-mers of all
+/// the inputs. The `failed` files, which could not be read, are left out.
+fn print_per_file_counts(
+    inputs: &[String],
+    collect_params: &CollectParams,
+    params: &CountParams,
+    distinct: usize,
+    failed: &[String],
+) -> Result<()> {
+    let params = single_file_params(params, false);
+    let mut rows = Vec::new();
+    for file in listed_files(inputs, failed)? {
+        let files = [file.clone()];
+        let buckets = collect_superkmers(collect_params, &files, None, None, None, None)?;
+        let shards = count_shards(buckets, &params)?;
+        let total: usize = shards.iter().map(|s| s.total).sum();
+        let count: usize = shards.iter().map(|s| s.distinct).sum();
+        rows.push((file, total, count));
+    }
+    let width = rows.iter().map(|(file, ..)| file.len()).fold(4, usize::max);
+    eprintln!(
+        "{:<width$}  {:>14}  {:>14}  {:>8}",
+        "file", "k-mers", "distinct", "of all"
+    );
+    for (file, total, count) in rows {
+        let percent = 100. * count as f64 / distinct.max(1) as f64;
+        eprintln!("{file:<width$}  {total:>14}  {count:>14}  {percent:>7.02}%");
+    }
+    Ok(())
+}
+
+/// Counts the k-mers present in every file listed by the `@FILE` inputs, each file being
+/// counted into fresh buckets. Returns their number and that of the files; the `failed`
+/// files, which could not be read, are left out.
+fn count_core(
+    inputs: &[String],
+    collect_params: &CollectParams,
+    params: &CountParams,
+    failed: &[String],
+) -> Result<(usize, usize)> {
+    let params = single_file_params(params, true);
+    // per shard, the number of files in which each k-mer of the first file appeared: only
+    // those found in all the files so far are kept
+    let mut presence: Vec<HashMap<KT, u16, FxBuildHasher>> = Vec::new();
+    let mut files = 0;
+    for file in listed_files(inputs, failed)? {
+        ensure!(
+            files < u16::MAX as usize,
+            "--core handles at most {} files",
+            u16::MAX
+        );
+        let buckets = collect_superkmers(collect_params, &[file], None, None, None, None)?;
+        let shards = count_shards(buckets, &params)?;
+        if files == 0 {
+            presence = shards
+                .iter()
+                .map(|shard| shard.kmers.iter().map(|&kmer| (kmer, 1)).collect())
+                .collect();
+        } else {
+            for (seen, shard) in presence.iter_mut().zip(&shards) {
+                for kmer in &shard.kmers {
+                    if let Some(n) = seen.get_mut(kmer) {
+                        *n += 1;
+                    }
                 }
+                seen.retain(|_, n| *n as usize == files + 1);
             }
         }
-        buckets
+        files += 1;
     }
-    else {
-        let processor = SuperkmerCollector {
-            k,
-            m,
-            buckets: &buckets,
-            match_n: &match_n,
-            match_newline: &match_newline,
-            min_pos_vec: vec![],
-            sk_pos_vec: vec![],
-        };
-        let (reader, _) = from_path(path).expect("Failed to open input file");
-        if is_fastq {
-            let reader = fastq::Reader::new(reader);
-            reader.process_parallel(processor, threads).unwrap();
+    let core = presence.iter().map(HashMap::len).sum();
+    Ok((core, files))
+}
+
+/// Writes the accumulation curve of the files listed by the `@FILE` inputs: the number of
+/// distinct k-mers of the first `n` files, for each `n`, each file being counted into fresh
+/// buckets. The `failed` files, which could not be read, are left out.
+fn write_accumulation_curve(
+    path: &str,
+    inputs: &[String],
+    collect_params: &CollectParams,
+    params: &CountParams,
+    failed: &[String],
+) -> Result<()> {
+    let params = single_file_params(params, true);
+    let mut out = open_output(path)?;
+    writeln!(out, "files\tfile\tcumulative_distinct")?;
+    // per shard, the distinct k-mers of the files so far
+    let mut seen: Vec<HashSet<KT, FxBuildHasher>> = Vec::new();
+    let mut files = 0;
+    for file in listed_files(inputs, failed)? {
+        let files_read = [file];
+        let buckets = collect_superkmers(collect_params, &files_read, None, None, None, None)?;
+        let shards = count_shards(buckets, &params)?;
+        seen.resize_with(shards.len(), HashSet::default);
+        for (seen, shard) in seen.iter_mut().zip(&shards) {
+            seen.extend(&shard.kmers);
         }
-        else {
-            let reader = fasta::Reader::new(reader);
-            reader.process_parallel(processor, threads).unwrap();
+        files += 1;
+        let distinct: usize = seen.iter().map(HashSet::len).sum();
+        writeln!(out, "{files}\t{}\t{distinct}", files_read[0])?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Fails if some of the listed input files could not be read, once the others were counted.
+fn check_failed(failed: Mutex<Vec<String>>) -> Result<()> {
+    let failed = failed.into_inner().unwrap();
+    ensure!(
+        failed.is_empty(),
+        "Failed to read {} of the listed files: {}",
+        failed.len(),
+        failed.join(", ")
+    );
+    Ok(())
+}
+
+/// Sums the per-shard abundance histograms into bins 0 to `histo_max`.
+fn merge_histograms(shards: &[ShardCount], histo_max: usize) -> Vec<usize> {
+    let mut histogram = vec![0; histo_max + 1];
+    for shard in shards {
+        for (bin, n) in shard.histogram.iter().enumerate() {
+            histogram[bin] += n;
         }
-        buckets
     }
+    histogram
+}
+
+/// Number of largest buckets listed by --shard-stats, and of shards by --singletons.
+const TOP_SHARDS: usize = 5;
+
+/// The peak resident set size of the process so far, in bytes, read from `/proc/self/status`
+/// (so only on Linux).
+fn peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let peak = status.lines().find_map(|l| l.strip_prefix("VmHWM:"))?;
+    let kib: u64 = peak.trim().strip_suffix("kB")?.trim().parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// Prints the peak memory of the process at the end of `phase`, if it is known.
+fn report_mem(phase: &str) {
+    if let Some(bytes) = peak_rss() {
+        let gib = bytes as f64 / (1u64 << 30) as f64;
+        eprintln!("Peak memory at the end of the {phase}: {gib:.03} GiB");
+    }
+}
+
+/// Prints the spread of the numbers of super-k-mers per bucket (those in memory, without the
+/// spilled ones) and the share of the largest buckets.
+fn print_shard_stats(buckets: &[Bucket]) {
+    let mut sizes: Vec<(usize, usize)> = buckets
+        .iter()
+        .map(|b| b.lock().unwrap().len())
+        .enumerate()
+        .collect();
+    let n = sizes.len() as f64;
+    let total: usize = sizes.iter().map(|&(_, size)| size).sum();
+    let mean = total as f64 / n;
+    let variance = sizes
+        .iter()
+        .map(|&(_, size)| (size as f64 - mean).powi(2))
+        .sum::<f64>()
+        / n;
+    let min = sizes.iter().map(|&(_, size)| size).min().unwrap_or(0);
+    let max = sizes.iter().map(|&(_, size)| size).max().unwrap_or(0);
+    let deviation = variance.sqrt();
+    eprintln!(
+        "Super-k-mers per bucket: {min} to {max}, mean {mean:.02}, deviation {deviation:.02}"
+    );
+    sizes.sort_unstable_by_key(|&(_, size)| std::cmp::Reverse(size));
+    for &(shard, size) in sizes.iter().take(TOP_SHARDS) {
+        let percent = 100. * size as f64 / total.max(1) as f64;
+        eprintln!("Bucket {shard}: {size} super-k-mers ({percent:.02}% of all)");
+    }
+}
+
+/// Prints the spread of the numbers of singleton k-mers per shard and the shards holding the
+/// most of them, with their share of the distinct k-mers of the shard.
+fn print_singleton_shards(shards: &[ShardCount]) {
+    let n = shards.len().max(1) as f64;
+    let total: usize = shards.iter().map(|s| s.singletons).sum();
+    let min = shards.iter().map(|s| s.singletons).min().unwrap_or(0);
+    let max = shards.iter().map(|s| s.singletons).max().unwrap_or(0);
+    let mean = total as f64 / n;
+    eprintln!("Singletons per shard: {min} to {max}, mean {mean:.02}");
+    let mut order: Vec<usize> = (0..shards.len()).collect();
+    order.sort_unstable_by_key(|&shard| std::cmp::Reverse(shards[shard].singletons));
+    for &shard in order.iter().take(TOP_SHARDS) {
+        let ShardCount {
+            singletons,
+            distinct,
+            ..
+        } = shards[shard];
+        let percent = 100. * singletons as f64 / distinct.max(1) as f64;
+        eprintln!("Shard {shard}: {singletons} singletons ({percent:.02}% of its distinct)");
+    }
+}
+
+/// Finds the main peak of the abundance histogram at or after `min_freq`: the highest local
+/// maximum, the last (capped) bin excluded. There is none if the histogram only decreases.
+fn coverage_peak(histogram: &[usize], min_freq: usize) -> Option<usize> {
+    (min_freq.max(1)..histogram.len().saturating_sub(1))
+        .filter(|&f| histogram[f - 1] <= histogram[f] && histogram[f] >= histogram[f + 1])
+        .max_by_key(|&f| histogram[f])
+}
+
+/// Writes the abundance histogram with all bins from 1 to `histo_max`, in the two-column
+/// layout of KMC that GenomeScope reads.
+fn write_histogram(path: &str, histogram: &[usize]) -> Result<()> {
+    let mut out = open_output(path)?;
+    for (freq, n) in histogram.iter().enumerate().skip(1) {
+        writeln!(out, "{freq}\t{n}")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Writes the k-mer spectrum of bins 1 to `histo_max` with a header and cumulative columns,
+/// `tail` being the occurrences of the k-mers of the last bin.
+fn write_spectrum(path: &str, histogram: &[usize], tail: usize) -> Result<()> {
+    let mut out = open_output(path)?;
+    writeln!(
+        out,
+        "frequency\tdistinct_kmers\tcumulative_distinct\tcumulative_total_occurrences"
+    )?;
+    let (mut distinct, mut total) = (0, 0);
+    let histo_max = histogram.len() - 1;
+    for (freq, &n) in histogram.iter().enumerate().skip(1) {
+        distinct += n;
+        total += if freq == histo_max { tail } else { freq * n };
+        writeln!(out, "{freq}\t{n}\t{distinct}\t{total}")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Longest k-mers of --bitset-out, whose bitset then takes 512 MiB.
+const MAX_BITSET_K: usize = 16;
+
+/// Writes the presence bitset of the k-mers, of 4^k bits: bit `kmer % 64` of the word
+/// `kmer / 64`, a little-endian `u64`, is set for each k-mer, so that a mmapped file answers
+/// membership queries in constant time.
+fn write_bitset(path: &str, kmers: impl Iterator<Item = KT>, k: usize) -> Result<()> {
+    let mut words = vec![0u64; (1usize << (2 * k)).div_ceil(64)];
+    for kmer in kmers {
+        let kmer = kmer as usize;
+        words[kmer / 64] |= 1 << (kmer % 64);
+    }
+    let mut out = open_output(path)?;
+    for word in words {
+        out.write_all(&word.to_le_bytes())?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Writes the distinct k-mers as raw little-endian `u64`s (`u128`s with the `wide` feature):
+/// 8 (16) bytes per k-mer and no header, so the file can be mmapped as a `[u64]` of length
+/// `file_size / 8`.
+/// Each k-mer is 2-bit encoded with its first base in the low bits (A=0, C=1, T=2, G=3).
+/// Shards are written one after the other in shard order and each of them is sorted
+/// ascending, so the file is a concatenation of sorted runs (a single one with --sorted).
+fn write_binary(path: &str, kmers: impl Iterator<Item = KT>) -> Result<()> {
+    let mut out = open_output(path)?;
+    for kmer in kmers {
+        out.write_all(&kmer.to_le_bytes())?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Merges the per-shard MinHash sketches into the `n` smallest hashes, ascending.
+fn merge_minhash(shards: &[ShardCount], n: usize) -> Vec<u64> {
+    let mut hashes: Vec<u64> = shards
+        .iter()
+        .flat_map(|s| s.minhash.iter().copied())
+        .collect();
+    hashes.sort_unstable();
+    hashes.truncate(n);
+    hashes
+}
+
+/// Writes a MinHash sketch as a `#` header line with its parameters followed by one hash
+/// per line, in ascending order.
+fn write_minhash(path: &str, hashes: &[u64], k: usize, canonical: bool, seed: u64) -> Result<()> {
+    let mut out = open_output(path)?;
+    let strands = if canonical { "canonical" } else { "forward" };
+    let size = hashes.len();
+    writeln!(
+        out,
+        "# k={k} {strands} hash=murmur3_fmix64 seed={seed} size={size}"
+    )?;
+    for hash in hashes {
+        writeln!(out, "{hash}")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Writes the numbers of `total` and `distinct` k-mers as `<name>=<number>` lines, the
+/// `distinct_kmers` one last, for scripts that only read the last line.
+fn write_count(path: &str, distinct: usize, total: usize) -> Result<()> {
+    let mut out = open_output(path)?;
+    writeln!(out, "total_kmers={total}")?;
+    writeln!(out, "distinct_kmers={distinct}")?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Writes the run summary as pretty-printed JSON.
+fn write_summary(path: &str, summary: &RunSummary) -> Result<()> {
+    let mut out = open_output(path)?;
+    serde_json::to_writer_pretty(&mut out, summary)?;
+    writeln!(out)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Unions indexes saved with --save-index with the same parameters
+#[derive(Parser, Debug)]
+#[command(name = "distinct-kmers merge")]
+struct MergeArgs {
+    /// Indexes to merge
+    #[arg(required = true, num_args = 1..)]
+    indexes: Vec<PathBuf>,
+    /// Merged index
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+/// Times the collection and the counting of a reproducible random genome, to compare the
+/// parameters and track performance without any data file
+#[derive(Parser, Debug)]
+#[command(name = "distinct-kmers bench")]
+struct BenchArgs {
+    /// Size of the random genome, in bases
+    #[arg(long, default_value_t = 100_000_000)]
+    size: usize,
+    /// Seed of the random genome
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// K-mer size
+    #[arg(short, default_value_t = 31)]
+    k: usize,
+    /// Minimizer size
+    #[arg(short, default_value_t = 21)]
+    m: usize,
+    /// Choose the minimizer size from k: 21, or k if smaller, or larger for the super-k-mers
+    /// to fit in a word
+    #[arg(long, conflicts_with = "m")]
+    auto_m: bool,
+    /// Number of threads [default: all]
+    #[arg(short, long)]
+    threads: Option<usize>,
+    /// Number of minimizer bases selecting the bucket of a super-k-mer: there are 4^N buckets
+    #[arg(long, default_value_t = DEFAULT_SHARD_BASES)]
+    shard_bases: usize,
+    /// How to find the distinct k-mers of each bucket
+    #[arg(long, value_enum, default_value_t = CountMethod::Hash)]
+    count_method: CountMethod,
+}
+
+/// Runs the `bench` subcommand.
+fn bench(args: BenchArgs) -> Result<()> {
+    // a temporary FASTA file, so that the collection times the whole reader path
+    let name = format!("distinct-kmers-bench-{}.fa", std::process::id());
+    let path = std::env::temp_dir().join(name);
+    let context = || format!("Failed to write {}", path.display());
+    let mut out = BufWriter::new(File::create(&path).with_context(context)?);
+    writeln!(out, ">random").with_context(context)?;
+    // splitmix64, 32 bases per draw
+    let mut state = args.seed;
+    let mut line = Vec::with_capacity(32);
+    for start in (0..args.size).step_by(32) {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        let bases = (args.size - start).min(32);
+        line.clear();
+        line.extend((0..bases).map(|i| b"ACGT"[(z >> (2 * i)) as usize & 3]));
+        out.write_all(&line).with_context(context)?;
+    }
+    writeln!(out).with_context(context)?;
+    out.flush().with_context(context)?;
+    drop(out);
+    let mut counter = DistinctKmerCounter::new(args.k);
+    counter.m = args.m;
+    counter.threads = args.threads.unwrap_or_else(current_num_threads);
+    counter.shard_bases = args.shard_bases;
+    counter.method = args.count_method;
+    let result = counter.count(&[path.to_string_lossy().into_owned()]);
+    std::fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    let result = result?;
+    let mbp = args.size as f64 / 1e6;
+    eprintln!(
+        "Collection: {:.02} s, {:.02} Mbp/s",
+        result.collect_seconds,
+        mbp / result.collect_seconds
+    );
+    eprintln!(
+        "Counting: {:.02} s, {:.02} Mbp/s",
+        result.count_seconds,
+        mbp / result.count_seconds
+    );
+    eprintln!(
+        "{} distinct {}-mers out of {}, in {} super-k-mers",
+        result.distinct, args.k, result.total, result.superkmers
+    );
+    Ok(())
+}
+
+/// Runs the `merge` subcommand.
+fn merge(args: MergeArgs) -> Result<()> {
+    let distinct = index::merge(&args.indexes, &args.output)?;
+    eprintln!("Number of distinct k-mers in the union: {distinct}");
+    Ok(())
+}
+
+/// Runs the `query` subcommand.
+fn query(args: QueryArgs) -> Result<()> {
+    let threads = args.threads.unwrap_or_else(current_num_threads);
+    let set = KmerSet::load(&args.set, args.k, !args.forward_only)?;
+    eprintln!(
+        "Loaded {} {}-mers from {}",
+        set.len(),
+        args.k,
+        args.set.display()
+    );
+    let out = RecordWriter::new(
+        open_output(&args.output).with_context(|| format!("Failed to create {}", args.output))?,
+    );
+    query_file(&set, &args.query, args.fastq, threads, &out)?;
+    let mut out = out.into_inner().unwrap();
+    out.flush()
+        .with_context(|| format!("Failed to write {}", args.output))
+}
+
+/// Reports the size of a saved index and writes its k-mers to the requested outputs.
+fn load_index(args: &Args, path: &str) -> Result<()> {
+    let (k, canonical) = (args.k, !args.forward_only);
+    let mut index = index::IndexReader::open(Path::new(path))?;
+    index.check(k, canonical)?;
+    log!(
+        1,
+        "Index counted with k = {k} and m = {} in {} shards",
+        index.header.m,
+        index.shards()
+    );
+    let shards = (0..index.shards())
+        .map(|i| {
+            let kmers = index.shard(i)?;
+            Ok::<_, io::Error>(ShardCount {
+                distinct: kmers.len(),
+                solid: kmers.len(),
+                kmers,
+                ..Default::default()
+            })
+        })
+        .collect::<io::Result<Vec<_>>>()
+        .with_context(|| format!("Failed to read index {path}"))?;
+    if canonical {
+        eprintln!("Number of distinct canonical {k}-mers: {}", index.len());
+    } else {
+        eprintln!("Number of distinct {k}-mers: {}", index.len());
+    }
+    let context = |path: &str| format!("Failed to write {path}");
+    let kmers = || entries(&shards, args.sorted).map(|(kmer, _)| kmer);
+    if let Some(path) = &args.output {
+        write_kmers(path, kmers(), k, false).with_context(|| context(path))?;
+    }
+    if let Some(path) = &args.fasta_out {
+        write_kmers(path, kmers(), k, true).with_context(|| context(path))?;
+    }
+    if let Some(path) = &args.binary_out {
+        write_binary(path, kmers()).with_context(|| context(path))?;
+    }
+    if let Some(path) = &args.bitset_out {
+        let kmers = entries(&shards, false).map(|(kmer, _)| kmer);
+        write_bitset(path, kmers, k).with_context(|| context(path))?;
+    }
+    if let Some(path) = &args.kff_out {
+        let n = index.len();
+        let out = open_output(path).with_context(|| context(path))?;
+        let kmers = entries(&shards, args.sorted);
+        kff::write(out, k, canonical, n, false, kmers).with_context(|| context(path))?;
+    }
+    Ok(())
 }
 
-fn main() {
+fn main() -> Result<()> {
+    // `distinct-kmers query ...` looks up k-mers instead of counting them,
+    // `distinct-kmers merge ...` unions indexes, `distinct-kmers bench ...` times a random genome
+    match std::env::args().nth(1).as_deref() {
+        Some("query") => return query(QueryArgs::parse_from(std::env::args().skip(1))),
+        Some("merge") => return merge(MergeArgs::parse_from(std::env::args().skip(1))),
+        Some("bench") => return bench(BenchArgs::parse_from(std::env::args().skip(1))),
+        _ => {}
+    }
     let args = Args::parse();
+    let verbosity = if args.quiet {
+        0
+    } else {
+        args.verbose.saturating_add(1)
+    };
+    set_verbosity(verbosity);
     let k = args.k;
-    assert!(k <= 32);
-    let m = args.m;
-    assert!(m <= k);
-    let w = k - m + 1;
-    let path = args.input;
-    let is_fastq = args.fastq;
+    let canonical = !args.forward_only;
+    ensure!(
+        args.bitset_out.is_none() || k <= MAX_BITSET_K,
+        "--bitset-out needs k <= {MAX_BITSET_K}, the bitset of {k}-mers would have 4^{k} bits"
+    );
+    if let (Some(min), Some(max)) = (args.min_count, args.max_count) {
+        ensure!(
+            min <= max,
+            "--min-count {min} is above --max-count {max}, no k-mer could be kept"
+        );
+    }
+    if let Some(path) = &args.load_index {
+        return load_index(&args, path);
+    }
     let threads = if let Some(t) = args.threads {
-        ThreadPoolBuilder::new()
-            .num_threads(t)
-            .build_global()
-            .unwrap();
+        ThreadPoolBuilder::new().num_threads(t).build_global()?;
         t
     } else {
         current_num_threads()
     };
-    eprintln!("Running using {threads} threads");
+    // a spaced seed of weight k is read from windows as long as its pattern
+    let (span, spaced) = match &args.spaced {
+        Some(pattern) => {
+            ensure!(
+                pattern.bytes().all(|c| c == b'0' || c == b'1'),
+                "the spaced seed pattern must be made of 0s and 1s"
+            );
+            let care: Vec<usize> = pattern
+                .bytes()
+                .enumerate()
+                .filter(|&(_, c)| c == b'1')
+                .map(|(i, _)| i)
+                .collect();
+            ensure!(
+                care.len() == k,
+                "the spaced seed pattern must have k = {k} 1s"
+            );
+            (pattern.len(), Some(care))
+        }
+        None => (k, None),
+    };
+    let m = if args.auto_m {
+        let m = auto_m(span);
+        log!(1, "Using minimizers of size m = {m} (--auto-m)");
+        m
+    } else {
+        args.m
+    };
+    // the numbers of G and C bases of the k-mers whose GC content is in the range
+    let gc = if args.min_gc.is_some() || args.max_gc.is_some() {
+        let (min_gc, max_gc) = (args.min_gc.unwrap_or(0.), args.max_gc.unwrap_or(1.));
+        let fits = |gc: &u32| (min_gc..=max_gc).contains(&(*gc as f64 / k as f64));
+        let (Some(first), Some(last)) = ((0..=k as u32).find(fits), (0..=k as u32).rfind(fits))
+        else {
+            bail!("No {k}-mer has a GC content between {min_gc} and {max_gc}");
+        };
+        Some(first..=last)
+    } else {
+        None
+    };
+    // the quality thresholds as the characters of the FASTQ records
+    let phred = |qual: Option<u8>, flag: &str| {
+        let offset = args.trim_phred_offset;
+        let to_char = |q: u8| {
+            q.checked_add(offset).with_context(|| {
+                format!("{flag} {q} plus --trim-phred-offset {offset} is over 255")
+            })
+        };
+        qual.map(to_char).transpose()
+    };
+    let trim_qual = phred(args.trim_qual, "--trim-qual")?;
+    let min_mean_qual = phred(args.min_mean_qual, "--min-mean-qual")?;
+    let mut collect_params = CollectParams {
+        k: span,
+        m,
+        canonical,
+        rna: args.rna,
+        is_fastq: args.fastq,
+        threads,
+        recursive: args.recursive,
+        min_length: args.min_length,
+        include_contigs: args.include_contigs.clone(),
+        exclude_contigs: args.exclude_contigs.clone(),
+        skip_masked: args.skip_masked,
+        upper_only: args.upper_only,
+        normalize_case: args.normalize_case,
+        chunk_bases: args.chunk_bases,
+        interleaved: args.interleaved,
+        strict: args.strict,
+        window: args
+            .window
+            .map(|window| (window, args.step.unwrap_or(window))),
+        trim_qual,
+        min_mean_qual,
+        progress: args.progress && io::stderr().is_terminal(),
+        bucket_cap: 0,
+        bucket_sizes: None,
+        spill_dir: args.spill_dir.clone(),
+        shard_bases: args.shard_bases,
+        subsample: args.subsample,
+        seed: args.seed,
+        max_records: args.max_records,
+        scheme: args.scheme,
+        syncmer_s: args.syncmer_s,
+    };
+    collect_params.check()?;
+    if !args.no_prealloc {
+        collect_params.bucket_cap = bucket_cap(args.max_mem, collect_params.shards());
+    }
+    let w = span - m + 1;
+    let parameters = Parameters {
+        k,
+        m,
+        w,
+        span,
+        spaced: args.spaced.clone(),
+        canonical,
+        threads,
+        count_threads: args.low_mem_count,
+        scheme: value_name(args.scheme),
+        syncmer_s: args.syncmer_s,
+        shard_bases: args.shard_bases,
+        shards: collect_params.shards(),
+        bucket_cap: collect_params.bucket_cap,
+        max_mem: args.max_mem,
+        count_method: value_name(args.count_method),
+        seed: args.seed,
+        min_length: args.min_length,
+        trim_qual: args.trim_qual,
+        min_mean_qual: args.min_mean_qual,
+        subsample: args.subsample,
+        max_records: args.max_records,
+        min_count: args.min_count,
+        max_count: args.max_count,
+        min_gc: args.min_gc,
+        max_gc: args.max_gc,
+        min_entropy: args.min_entropy,
+        scaled: args.scaled,
+    };
+    if args.info {
+        let block = serde_json::to_string_pretty(&parameters)?;
+        eprintln!("Effective parameters: {block}");
+    }
+    log!(1, "Running using {threads} threads");
+    if !args.two_pass {
+        log!(
+            1,
+            "Reserving {} super-k-mers per bucket",
+            collect_params.bucket_cap
+        );
+    }
+    ensure!(
+        args.input2.is_none() || args.input.len() == 1,
+        "--input2 holds the mates of a single input"
+    );
+    let mut inputs = args.input.clone();
+    for pattern in &args.glob {
+        inputs.extend(expand_glob(pattern)?);
+    }
+    let listed = inputs.iter().any(|path| path.starts_with('@'));
+    ensure!(
+        !args.per_file_counts || listed,
+        "--per-file-counts needs an @FILE input"
+    );
+    ensure!(!args.core || listed, "--core needs an @FILE input");
+    ensure!(
+        args.accumulation_curve.is_none() || listed,
+        "--accumulation-curve needs an @FILE input"
+    );
+    if args.report_mem && peak_rss().is_none() {
+        eprintln!("Warning: the memory cannot be measured on this platform, ignoring --report-mem");
+    }
+    if args.check {
+        let failed = Mutex::default();
+        let mates = args.input2.as_deref();
+        let (records, bases) = check_inputs(&collect_params, &inputs, mates, Some(&failed))?;
+        eprintln!("Number of records: {records}");
+        eprintln!("Number of bases: {bases}");
+        return check_failed(failed);
+    }
     let start_collect = Instant::now();
-    let buckets = collect_superkmers(k, m, path, threads, is_fastq);
-    let elapsed = start_collect.elapsed().as_secs_f64();
-    eprintln!("Collected super-k-mers in {:.02} s", elapsed);
-    let kmer_mask = (1u128 << (2 * k)) - 1;
+    if args.two_pass {
+        ensure!(
+            !inputs.iter().chain(&args.input2).any(|path| path == "-"),
+            "--two-pass reads the inputs twice, so they cannot be stdin"
+        );
+        let sizes = size_buckets(&collect_params, &inputs, args.input2.as_deref(), true)?;
+        let superkmers: usize = sizes.iter().sum();
+        log!(
+            1,
+            "Reserving {superkmers} super-k-mers, as counted by the first pass"
+        );
+        collect_params.bucket_sizes = Some(sizes);
+    }
+    let minimizers = args
+        .minimizer_stats
+        .then(|| Mutex::new(MinimizerCounts::default()));
+    let per_record = match &args.per_record {
+        Some(path) => Some(RecordWriter::new(
+            open_output(path).with_context(|| format!("Failed to create {path}"))?,
+        )),
+        None => None,
+    };
+    let failed = Mutex::default();
+    let buckets = collect_superkmers(
+        &collect_params,
+        &inputs,
+        args.input2.as_deref(),
+        minimizers.as_ref(),
+        per_record.as_ref(),
+        Some(&failed),
+    )?;
+    // the exact sizes are those of all the inputs: the single files and the --compare input
+    // collected again below only reserve --max-mem
+    collect_params.bucket_sizes = None;
+    let collect_elapsed = start_collect.elapsed().as_secs_f64();
+    log!(1, "Collected super-k-mers in {:.02} s", collect_elapsed);
+    if args.report_mem {
+        report_mem("collection");
+    }
+    if let Some(out) = per_record {
+        // the buckets were not filled, there is nothing left to count
+        let mut out = out.into_inner().unwrap();
+        out.flush().context("Failed to write per-record counts")?;
+        return check_failed(failed);
+    }
+    if let Some(minimizers) = minimizers {
+        let minimizers = minimizers.into_inner().unwrap();
+        let superkmers: usize = minimizers.values().sum();
+        let max = minimizers.values().max().unwrap_or(&0);
+        let mean = superkmers as f64 / minimizers.len().max(1) as f64;
+        eprintln!("Number of distinct minimizers: {}", minimizers.len());
+        eprintln!("Super-k-mers per minimizer: {mean:.02} on average, {max} at most");
+    }
+    if args.shard_stats {
+        print_shard_stats(&buckets);
+    }
+    let bucket_bytes = buckets
+        .iter()
+        .map(|b| b.lock().unwrap().capacity() * size_of::<SKT>())
+        .sum();
     let start_count = Instant::now();
-    let count: usize = buckets
-        .into_par_iter()
-        .map(|v| {
-            let v = v.into_inner().unwrap();
-            let mut set =
-                HashSet::with_capacity_and_hasher(v.len() * (w + 1) * 3 / 5, FxBuildHasher);
-            for skmer in v {
-                let len = (skmer & SKLEN_MASK) as usize;
-                let skmer = skmer >> SKLEN_BITS;
-                for i in 0..(len - k + 1) {
-                    let kmer = ((skmer >> (2 * i)) & kmer_mask) as KT; // start with low bits
-                    set.insert(kmer);
-                }
+    let with_counts = args.counts
+        || args.dump
+        || args.histogram.is_some()
+        || args.spectrum.is_some()
+        || args.estimate_coverage
+        || args.min_count.is_some()
+        || args.max_count.is_some()
+        || args.singletons
+        || args.unique_bed.is_some();
+    let params = CountParams {
+        k: span,
+        w,
+        canonical,
+        keep_kmers: args.output.is_some()
+            || args.fasta_out.is_some()
+            || args.binary_out.is_some()
+            || args.bitset_out.is_some()
+            || args.unique_bed.is_some()
+            || args.kff_out.is_some()
+            || args.dump
+            || args.diff_out.is_some()
+            || args.save_index.is_some(),
+        sort_kmers: args.binary_out.is_some() || args.save_index.is_some() || args.sorted,
+        histo_max: (args.histogram.is_some() || args.spectrum.is_some() || args.estimate_coverage)
+            .then_some(args.histo_max),
+        min_count: args.min_count,
+        max_count: args.max_count,
+        scaled: args.scaled,
+        counts: with_counts,
+        strands: args.strand_report,
+        spill_dir: args.spill_dir,
+        method: args.count_method,
+        map_capacity: args.map_capacity,
+        minhash: args.minhash,
+        seed: args.seed,
+        spaced,
+        gc,
+        min_entropy: args.min_entropy,
+        distinct_superkmers: args.count_superkmers,
+        palindromes: args.palindromes,
+        gc_histogram: args.gc_summary,
+        // the sorted k-mers do not depend on the order in which they were counted
+        stable: args.stable && !args.sorted,
+        concurrent_shards: args.low_mem_count,
+    };
+    if let Some(other) = args.compare.as_ref().or(args.diff.as_ref()) {
+        let other_buckets = collect_superkmers(
+            &collect_params,
+            std::slice::from_ref(other),
+            None,
+            None,
+            None,
+            None,
+        )?;
+        let comparison = compare_shards(buckets, other_buckets, &params)?;
+        let count_elapsed = start_count.elapsed().as_secs_f64();
+        log!(1, "Compared in {:.02} s", count_elapsed);
+        let [a, b] = comparison.distinct;
+        eprintln!("Number of distinct {k}-mers: {a} in the input, {b} in {other}");
+        eprintln!("Shared {k}-mers: {}", comparison.shared);
+        if args.compare.is_some() {
+            eprintln!("Jaccard index: {:.06}", comparison.jaccard());
+            let (a_in_b, b_in_a) = (comparison.containment(0), comparison.containment(1));
+            eprintln!("Containment of the input in {other}: {a_in_b:.06}");
+            eprintln!("Containment of {other} in the input: {b_in_a:.06}");
+        } else {
+            eprintln!("{k}-mers only in the input: {}", comparison.only(0));
+            eprintln!("{k}-mers only in {other}: {}", comparison.only(1));
+        }
+        if let Some(path) = &args.diff_out {
+            let mut kmers = comparison.only_first;
+            if args.sorted {
+                kmers.sort_unstable();
             }
-            set.len()
-        })
-        .sum();
-    let elapsed = start_count.elapsed().as_secs_f64();
-    eprintln!("Parallel count in {:.02} s", elapsed);
-    eprintln!("Number of distinct {k}-mers: {count}");
+            let kmers = kmers.into_iter();
+            write_kmers(path, kmers, k, false)
+                .with_context(|| format!("Failed to write {path}"))?;
+        }
+        return check_failed(failed);
+    }
+    if args.estimate {
+        let sketch = estimate_distinct(buckets, &params, args.hll_precision)?;
+        let count_elapsed = start_count.elapsed().as_secs_f64();
+        log!(1, "Estimated in {:.02} s", count_elapsed);
+        let error = 100. * sketch.relative_error();
+        let estimate = sketch.estimate() * params.scaled.unwrap_or(1) as f64;
+        eprintln!(
+            "Estimated number of distinct {k}-mers: {estimate:.0} (expected error {error:.02}%)"
+        );
+        return check_failed(failed);
+    }
+    if let Some(bits) = args.bloom_bits {
+        let count = count_with_bloom(buckets, &params, bits)?;
+        let count_elapsed = start_count.elapsed().as_secs_f64();
+        log!(1, "Approximate count in {:.02} s", count_elapsed);
+        let count = count as u64 * params.scaled.unwrap_or(1);
+        eprintln!("Approximate number of distinct {k}-mers: {count} (a lower bound)");
+        return check_failed(failed);
+    }
+    let shards = count_shards(buckets, &params)?;
+    let count: usize = shards.iter().map(|s| s.distinct).sum();
+    if args.strand_report {
+        let [forward, reverse, both] =
+            from_fn(|i| shards.iter().map(|s| s.strands[i]).sum::<usize>());
+        eprintln!("Forward strand only: {forward}");
+        eprintln!("Reverse strand only: {reverse}");
+        eprintln!("Both strands: {both}");
+    }
+    let count_elapsed = start_count.elapsed().as_secs_f64();
+    log!(1, "Parallel count in {:.02} s", count_elapsed);
+    if args.report_mem {
+        report_mem("counting");
+    }
+    if canonical {
+        eprintln!("Number of distinct canonical {k}-mers: {count}");
+    } else {
+        eprintln!("Number of distinct {k}-mers: {count}");
+    }
+    if let Some(scaled) = args.scaled {
+        let estimate = count as u64 * scaled;
+        eprintln!("Estimated number of distinct {k}-mers (1/{scaled} of them sampled): {estimate}");
+    }
+    let superkmers: usize = shards.iter().map(|s| s.superkmers).sum();
+    let total: usize = shards.iter().map(|s| s.total).sum();
+    eprintln!("Number of super-k-mers: {superkmers}");
+    if args.count_superkmers {
+        let distinct: usize = shards.iter().map(|s| s.distinct_superkmers).sum();
+        eprintln!("Number of distinct super-k-mers: {distinct}");
+    }
+    eprintln!("Total number of {k}-mers: {total}");
+    if params.gc.is_some() {
+        let removed: usize = shards.iter().map(|s| s.gc_filtered).sum();
+        eprintln!("Removed {removed} {k}-mers (with multiplicity) outside of the GC content range");
+    }
+    if args.gc_summary {
+        let histogram: Vec<usize> = (0..=k)
+            .map(|gc| shards.iter().map(|s| s.gc_histogram[gc]).sum())
+            .collect();
+        let gc_bases: usize = histogram.iter().enumerate().map(|(gc, n)| gc * n).sum();
+        let mean = gc_bases as f64 / count.max(1) as f64;
+        eprintln!("GC content of the distinct {k}-mers (G and C bases, k-mers, share):");
+        for (gc, &n) in histogram.iter().enumerate() {
+            let percent = 100. * n as f64 / count.max(1) as f64;
+            eprintln!("{gc}\t{n}\t{percent:.02}%");
+        }
+        eprintln!("Mean GC content: {:.02}%", 100. * mean / k as f64);
+    }
+    if let Some(min_entropy) = params.min_entropy {
+        let removed: usize = shards.iter().map(|s| s.low_entropy).sum();
+        eprintln!("Removed {removed} {k}-mers (with multiplicity) of entropy below {min_entropy}");
+    }
+    if args.estimate_coverage {
+        let histogram = merge_histograms(&shards, args.histo_max);
+        if let Some(coverage) = coverage_peak(&histogram, args.peak_min_freq) {
+            eprintln!("Estimated {k}-mer coverage: {coverage}");
+            eprintln!("Estimated genome size: {}", total / coverage);
+        } else {
+            eprintln!("No coverage peak found in the abundance histogram");
+        }
+    }
+    if args.palindromes {
+        let palindromes: usize = shards.iter().map(|s| s.palindromes).sum();
+        eprintln!("Number of palindromic {k}-mers: {palindromes}");
+    }
+    if args.singletons {
+        let singletons: usize = shards.iter().map(|s| s.singletons).sum();
+        let percent = 100. * singletons as f64 / count.max(1) as f64;
+        eprintln!("Number of singleton {k}-mers: {singletons} ({percent:.02}% of distinct)");
+        print_singleton_shards(&shards);
+    }
+    if let Some(min_count) = args.min_count {
+        let removed: usize = shards.iter().map(|s| s.below_min).sum();
+        eprintln!("Removed {removed} {k}-mers occurring less than {min_count} times");
+    }
+    if let Some(max_count) = args.max_count {
+        let removed: usize = shards.iter().map(|s| s.above_max).sum();
+        eprintln!("Removed {removed} {k}-mers occurring more than {max_count} times");
+    }
+    if args.min_count.is_some() || args.max_count.is_some() {
+        let solid: usize = shards.iter().map(|s| s.solid).sum();
+        eprintln!("Number of solid {k}-mers: {solid}");
+    }
+    let context = |path: &str| format!("Failed to write {path}");
+    let kmers = || entries(&shards, args.sorted).map(|(kmer, _)| kmer);
+    if let Some(path) = args.output {
+        write_kmers(&path, kmers(), k, false).with_context(|| context(&path))?;
+    }
+    if let Some(path) = args.fasta_out {
+        write_kmers(&path, kmers(), k, true).with_context(|| context(&path))?;
+    }
+    if let Some(path) = args.binary_out {
+        write_binary(&path, kmers()).with_context(|| context(&path))?;
+    }
+    if let Some(path) = args.bitset_out {
+        let kmers = entries(&shards, false).map(|(kmer, _)| kmer);
+        write_bitset(&path, kmers, k).with_context(|| context(&path))?;
+    }
+    if let (Some(path), Some(reference)) = (&args.unique_bed, &args.reference) {
+        let unique = entries(&shards, false)
+            .filter(|&(_, count)| count == 1)
+            .map(|(kmer, _)| kmer)
+            .collect();
+        let set = KmerSet::new(unique, k, canonical);
+        let mut out = open_output(path).with_context(|| context(path))?;
+        let (intervals, positions) = write_bed(&set, reference, false, &mut out)?;
+        out.flush().with_context(|| context(path))?;
+        eprintln!("Positions of {reference} starting a unique {k}-mer: {positions}");
+        eprintln!("Unique intervals: {intervals}");
+    }
+    if args.dump {
+        let entries = entries(&shards, args.sorted);
+        write_dump(entries, k, &args.dump_sep).context("Failed to write the dump")?;
+    }
+    if let Some(path) = &args.histogram {
+        let histogram = merge_histograms(&shards, args.histo_max);
+        write_histogram(path, &histogram).with_context(|| context(path))?;
+    }
+    if let Some(path) = &args.spectrum {
+        let histogram = merge_histograms(&shards, args.histo_max);
+        let tail = shards.iter().map(|s| s.histogram_tail).sum();
+        write_spectrum(path, &histogram, tail).with_context(|| context(path))?;
+    }
+    if let (Some(n), Some(path)) = (args.minhash, &args.minhash_out) {
+        let hashes = merge_minhash(&shards, n);
+        write_minhash(path, &hashes, k, canonical, args.seed).with_context(|| context(path))?;
+    }
+    if let Some(path) = args.kff_out {
+        let kmers = entries(&shards, args.sorted);
+        let n = shards.iter().map(|s| s.kmers.len() as u64).sum();
+        let out = open_output(&path).with_context(|| context(&path))?;
+        kff::write(out, k, canonical, n, with_counts, kmers).with_context(|| context(&path))?;
+    }
+    if let Some(path) = &args.save_index {
+        let syncmers = args.scheme == Scheme::Syncmer;
+        let header = index::Header {
+            k,
+            m,
+            canonical,
+            seed: args.seed,
+            scheme: args.scheme,
+            syncmer_s: if syncmers { args.syncmer_s } else { 0 },
+            spaced: params
+                .spaced
+                .iter()
+                .flatten()
+                .fold(0, |mask, &i| mask | 1 << i),
+        };
+        let save = || {
+            let mut writer = index::IndexWriter::create(Path::new(path), &header, shards.len())?;
+            for shard in &shards {
+                writer.push_shard(&shard.kmers)?;
+            }
+            writer.finish()
+        };
+        save().with_context(|| context(path))?;
+    }
+    if args.per_file_counts {
+        let failed = failed.lock().unwrap();
+        print_per_file_counts(&inputs, &collect_params, &params, count, &failed)?;
+    }
+    if args.core {
+        let failed = failed.lock().unwrap();
+        let (core, files) = count_core(&inputs, &collect_params, &params, &failed)?;
+        eprintln!("{k}-mers present in all the {files} listed files: {core}");
+    }
+    if let Some(path) = &args.accumulation_curve {
+        let failed = failed.lock().unwrap();
+        write_accumulation_curve(path, &inputs, &collect_params, &params, &failed)
+            .with_context(|| context(path))?;
+    }
+    if let Some(path) = args.json {
+        let summary = RunSummary {
+            k,
+            m,
+            threads,
+            inputs,
+            superkmers,
+            total_kmers: total,
+            distinct_kmers: count,
+            collect_seconds: collect_elapsed,
+            count_seconds: count_elapsed,
+            estimated_peak_memory_bytes: bucket_bytes,
+            parameters,
+        };
+        write_summary(&path, &summary).with_context(|| context(&path))?;
+    }
+    // after every other output, which may also go to stdout
+    if let Some(path) = &args.count_out {
+        write_count(path, count, total).with_context(|| context(path))?;
+    }
+    check_failed(failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn compressed_dumps_read_back() {
+        let kmers = [0, 1, 0b11_10_01, KT::MAX >> 2];
+        let mut expected = vec![];
+        for &kmer in &kmers {
+            decode(kmer, 31, &mut expected);
+            expected.push(b'\n');
+        }
+        for ext in ["gz", "zst", "bz2", "xz"] {
+            let name = format!("distinct-kmers-test-{}.txt.{ext}", std::process::id());
+            let path = std::env::temp_dir().join(name);
+            let path = path.to_str().unwrap();
+            write_kmers(path, kmers.into_iter(), 31, false).unwrap();
+            let (mut reader, format) = niffler::send::from_path(path).unwrap();
+            let mut dump = vec![];
+            reader.read_to_end(&mut dump).unwrap();
+            std::fs::remove_file(path).unwrap();
+            assert_ne!(format, Format::No, "{ext}");
+            assert!(dump == expected, "{ext}");
+        }
+    }
 }
@@ -0,0 +1,110 @@
+//! 256-bit super-k-mers for the `wide` feature (k up to 64), with just the bit operations
+//! needed to pack them and to slide over their k-mers.
+
+use std::ops::{BitAnd, BitOr, BitOrAssign, Shl, Shr, ShrAssign};
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct U256 {
+    hi: u128,
+    lo: u128,
+}
+
+impl U256 {
+    pub const BITS: u32 = 256;
+
+    /// The low 128 bits.
+    pub fn low(self) -> u128 {
+        self.lo
+    }
+
+    pub fn to_le_bytes(self) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        bytes[..16].copy_from_slice(&self.lo.to_le_bytes());
+        bytes[16..].copy_from_slice(&self.hi.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_le_bytes(bytes: [u8; 32]) -> Self {
+        Self {
+            hi: u128::from_le_bytes(bytes[16..].try_into().unwrap()),
+            lo: u128::from_le_bytes(bytes[..16].try_into().unwrap()),
+        }
+    }
+}
+
+impl From<u64> for U256 {
+    fn from(x: u64) -> Self {
+        Self {
+            hi: 0,
+            lo: x as u128,
+        }
+    }
+}
+
+impl Shl<usize> for U256 {
+    type Output = Self;
+    fn shl(self, n: usize) -> Self {
+        match n {
+            0 => self,
+            1..=127 => Self {
+                hi: (self.hi << n) | (self.lo >> (128 - n)),
+                lo: self.lo << n,
+            },
+            128..=255 => Self {
+                hi: self.lo << (n - 128),
+                lo: 0,
+            },
+            _ => Self::default(),
+        }
+    }
+}
+
+impl Shr<usize> for U256 {
+    type Output = Self;
+    fn shr(self, n: usize) -> Self {
+        match n {
+            0 => self,
+            1..=127 => Self {
+                hi: self.hi >> n,
+                lo: (self.lo >> n) | (self.hi << (128 - n)),
+            },
+            128..=255 => Self {
+                hi: 0,
+                lo: self.hi >> (n - 128),
+            },
+            _ => Self::default(),
+        }
+    }
+}
+
+impl ShrAssign<usize> for U256 {
+    fn shr_assign(&mut self, n: usize) {
+        *self = *self >> n;
+    }
+}
+
+impl BitOr for U256 {
+    type Output = Self;
+    fn bitor(self, other: Self) -> Self {
+        Self {
+            hi: self.hi | other.hi,
+            lo: self.lo | other.lo,
+        }
+    }
+}
+
+impl BitOrAssign for U256 {
+    fn bitor_assign(&mut self, other: Self) {
+        *self = *self | other;
+    }
+}
+
+impl BitAnd for U256 {
+    type Output = Self;
+    fn bitand(self, other: Self) -> Self {
+        Self {
+            hi: self.hi & other.hi,
+            lo: self.lo & other.lo,
+        }
+    }
+}
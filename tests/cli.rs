@@ -0,0 +1,99 @@
+use std::process::Command;
+
+#[test]
+fn count_is_the_last_stdout_line() {
+    let path = std::env::temp_dir().join(format!("distinct-kmers-cli-{}.fa", std::process::id()));
+    // 10 k-mers, 3 canonical ones: ACGTA and TACGT, CGTAC and GTACG, then AAAAA
+    std::fs::write(&path, ">r0\nACGTACGTACGT\n>r1\nTTTTTT\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_distinct-kmers"))
+        .args(["-k", "5", "-m", "3", "-t", "4", "--dump"])
+        .arg(&path)
+        .arg("--count-out")
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines[lines.len() - 2..],
+        ["total_kmers=10", "distinct_kmers=3"]
+    );
+    assert_eq!(lines.len(), 3 + 2);
+    assert!(stdout.ends_with("distinct_kmers=3\n"));
+}
+
+#[test]
+fn count_out_needs_an_exact_count() {
+    for mode in [&["--estimate"][..], &["--bloom-bits", "1000"], &["--check"]] {
+        let output = Command::new(env!("CARGO_BIN_EXE_distinct-kmers"))
+            .args(["-k", "5", "-m", "3", "-"])
+            .args(mode)
+            .arg("--count-out")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2), "{mode:?}");
+        assert!(output.stdout.is_empty(), "{mode:?}");
+    }
+}
+
+#[test]
+fn min_count_above_max_count_is_refused() {
+    let output = Command::new(env!("CARGO_BIN_EXE_distinct-kmers"))
+        .args([
+            "-k",
+            "5",
+            "-m",
+            "3",
+            "--min-count",
+            "10",
+            "--max-count",
+            "5",
+            "-",
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("--min-count 10 is above --max-count 5"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn singletons_are_reported_per_shard() {
+    let path = std::env::temp_dir().join(format!(
+        "distinct-kmers-singletons-{}.fa",
+        std::process::id()
+    ));
+    // 6 canonical k-mers: the 3 of r0 and r1 occur more than once, the 3 of r2 once
+    std::fs::write(&path, ">r0\nACGTACGTACGT\n>r1\nTTTTTT\n>r2\nGATTACA\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_distinct-kmers"))
+        .args(["-k", "5", "-m", "3", "--singletons"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert!(
+        stderr.contains("Number of singleton 5-mers: 3 (50.00% of distinct)"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("Singletons per shard: 0 to "), "{stderr}");
+    // the shards with the most singletons hold all 3 of them
+    let per_shard: usize = stderr
+        .lines()
+        .filter_map(|line| line.strip_prefix("Shard "))
+        .map(|line| {
+            let (_, rest) = line.split_once(": ").unwrap();
+            rest.split(' ').next().unwrap().parse::<usize>().unwrap()
+        })
+        .sum();
+    assert_eq!(per_shard, 3, "{stderr}");
+}